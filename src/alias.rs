@@ -0,0 +1,168 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Words that don't help telling clubs apart, e.g. `Arsenal FC` and `Arsenal`
+const IGNORED_WORDS: [&str; 2] = ["fc", "afc"];
+
+/// How a spelling of a team name was linked to a team
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AliasSource {
+    /// Listed in the alias mapping file
+    AliasFile,
+    /// Same name as the team after normalization
+    Normalized,
+}
+
+impl AliasSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AliasSource::AliasFile => "alias_file",
+            AliasSource::Normalized => "normalized",
+        }
+    }
+
+    pub fn from_name(source: &str) -> Option<AliasSource> {
+        match source {
+            "alias_file" => Some(AliasSource::AliasFile),
+            "normalized" => Some(AliasSource::Normalized),
+            _ => None,
+        }
+    }
+}
+
+/// Maps alternative spellings of team names to their canonical name
+#[derive(Default)]
+pub struct AliasMap {
+    /// Normalized alias -> canonical name
+    aliases: HashMap<String, String>,
+    /// Aliases as they were spelled in the mapping file, along with their canonical name
+    entries: Vec<(String, String)>,
+}
+
+impl AliasMap {
+    /// Loads a JSON file mapping canonical team names to their aliases, e.g.
+    /// `{"Manchester United FC": ["Man United", "Manchester Utd"]}`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let content = fs::read_to_string(path)?;
+        let mapping: HashMap<String, Vec<String>> = serde_json::from_str(&content)?;
+
+        // Sorted, so aliases are stored in the same order on every run
+        let mut mapping: Vec<(String, Vec<String>)> = mapping.into_iter().collect();
+        mapping.sort();
+
+        let mut alias_map = AliasMap::default();
+        for (canonical, aliases) in mapping {
+            for alias in aliases {
+                alias_map.insert(&alias, &canonical);
+            }
+        }
+
+        Ok(alias_map)
+    }
+
+    pub fn insert(&mut self, alias: &str, canonical: &str) {
+        self.aliases.insert(normalize_team_name(alias), canonical.to_string());
+        self.entries.push((alias.to_string(), canonical.to_string()));
+    }
+
+    /// Returns the canonical name for a spelling, if it is a known alias
+    pub fn get_canonical_name(&self, name: &str) -> Option<&str> {
+        self.aliases.get(&normalize_team_name(name)).map(|c| c.as_str())
+    }
+
+    /// Returns the canonical name for an alias spelled exactly as in the mapping file
+    pub fn get_canonical_name_as_spelled(&self, name: &str) -> Option<&str> {
+        self.entries.iter()
+            .find(|(alias, _)| alias == name)
+            .map(|(_, canonical)| canonical.as_str())
+    }
+
+    /// Returns all aliases, as spelled in the mapping file, along with their canonical name
+    pub fn get_entries(&self) -> &Vec<(String, String)> {
+        &self.entries
+    }
+}
+
+/// Normalizes a team name for comparison: removes diacritics, punctuation, case and
+/// FC/AFC, e.g. `1. FC Köln` -> `1 koln`
+pub fn normalize_team_name(name: &str) -> String {
+    // Dots and apostrophes are dropped rather than splitting words, so that F.C. becomes FC
+    let without_diacritics: String = name.nfd()
+        .filter(|c| !is_combining_mark(*c) && *c != '.' && *c != '\'')
+        .collect();
+
+    without_diacritics.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !IGNORED_WORDS.contains(word))
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Similarity of two team names between 0 (nothing in common) and 1 (same name after normalization)
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize_team_name(a).chars().collect();
+    let b: Vec<char> = normalize_team_name(b).chars().collect();
+
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+
+    1.0 - levenshtein(&a, &b) as f64 / max_len as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_team_name_ignores_case_punctuation_diacritics_and_fc() {
+        assert_eq!(normalize_team_name("1. FC Köln"), "1 koln");
+        assert_eq!(normalize_team_name("FC Bayern München"), "bayern munchen");
+        assert_eq!(normalize_team_name("Liverpool F.C."), "liverpool");
+        assert_eq!(normalize_team_name("AFC Bournemouth"), "bournemouth");
+        assert_eq!(normalize_team_name("Brighton & Hove Albion"), "brighton hove albion");
+        assert_eq!(normalize_team_name("Newell's Old Boys"), "newells old boys");
+        assert_eq!(normalize_team_name("FC"), "");
+    }
+
+    #[test]
+    fn name_similarity_compares_normalized_names() {
+        assert_eq!(name_similarity("Liverpool FC", "Liverpool"), 1.0);
+        assert_eq!(name_similarity("FC", "AFC"), 1.0);
+        assert!(name_similarity("Arsenal", "Chelsea") < 0.5);
+        assert_eq!(name_similarity("Man United", "Man Utd"), 1.0 - 3.0 / 10.0);
+
+        let similar = name_similarity("Manchester United", "Manchester City");
+        assert!(similar > 0.5 && similar < 1.0, "{}", similar);
+    }
+
+    #[test]
+    fn alias_map_finds_normalized_and_exact_spellings() {
+        let mut alias_map = AliasMap::default();
+        alias_map.insert("Man Utd", "Manchester United FC");
+
+        assert_eq!(alias_map.get_canonical_name("man utd."), Some("Manchester United FC"));
+        assert_eq!(alias_map.get_canonical_name_as_spelled("Man Utd"), Some("Manchester United FC"));
+        assert_eq!(alias_map.get_canonical_name_as_spelled("man utd."), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{fmt, fs, path::{Path, PathBuf}};
+
+use log::{info, warn};
+use serde_json::Value;
+
+/// Codes of international competitions, used as the whole code of a league file or its first part,
+/// e.g. `worldcup.json`, `cl.json` or `uefa.el.json`
+const INTERNATIONAL_CODES: [&str; 10] = [
+    "cl", "clubworldcup", "concacaf", "conf", "conmebol", "copa", "el", "euro", "uefa", "worldcup",
+];
+
+/// What a football.json file contains
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileKind {
+    /// List of clubs (e.g. en.1.clubs.json)
+    Clubs,
+    /// Matches of a league or tournament (e.g. en.1.json)
+    League,
+    /// Group definitions of a tournament (e.g. worldcup.groups.json)
+    Groups,
+    /// Anything that could not be recognized
+    Unknown,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileKind::Clubs => write!(f, "clubs"),
+            FileKind::League => write!(f, "league"),
+            FileKind::Groups => write!(f, "groups"),
+            FileKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Classifies a file by its name. Falls back to looking at the content when the name
+/// does not follow the football.json naming conventions.
+pub fn classify_file(path: &Path) -> FileKind {
+    let kind = classify_file_name(path);
+    if kind != FileKind::Unknown {
+        return kind;
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to read: {} because: {}", path.to_string_lossy(), e.to_string());
+            return FileKind::Unknown;
+        }
+    };
+
+    let kind = classify_content(&content);
+    info!("Classified {} as {} by its content", path.to_string_lossy(), kind);
+    kind
+}
+
+/// Classifies a file by its name only, e.g. `en.1.clubs.json`, `worldcup.groups.json` or `en.1.json`.
+/// Other `<name>.json` files, e.g. `package.json`, are left to classify_content.
+pub fn classify_file_name(path: &Path) -> FileKind {
+    let file_name = match path.file_name() {
+        Some(n) => n.to_string_lossy(),
+        None => return FileKind::Unknown,
+    };
+
+    let stem = match file_name.strip_suffix(".json") {
+        Some(s) => s,
+        None => return FileKind::Unknown,
+    };
+
+    if let Some(code) = stem.strip_suffix(".clubs") {
+        if is_competition_code(code) {
+            return FileKind::Clubs;
+        }
+    } else if let Some(code) = stem.strip_suffix(".groups") {
+        if is_competition_code(code) {
+            return FileKind::Groups;
+        }
+    } else if is_league_code(stem) {
+        return FileKind::League;
+    }
+
+    FileKind::Unknown
+}
+
+/// Classifies JSON content by the keys of its top level object
+pub fn classify_content(content: &str) -> FileKind {
+    let value: Value = match serde_json::from_str(content) {
+        Ok(v) => v,
+        Err(_) => return FileKind::Unknown,
+    };
+
+    let object = match value.as_object() {
+        Some(o) => o,
+        None => return FileKind::Unknown,
+    };
+
+    if object.contains_key("clubs") {
+        FileKind::Clubs
+    } else if object.contains_key("groups") {
+        FileKind::Groups
+    } else if object.contains_key("matches") || object.contains_key("rounds") {
+        FileKind::League
+    } else {
+        FileKind::Unknown
+    }
+}
+
+/// Returns the file that belongs together with the given one: the league file for a groups
+/// file (`worldcup.groups.json` -> `worldcup.json`) and vice versa.
+pub fn companion_file(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+
+    let companion_name = match classify_file_name(path) {
+        FileKind::Groups => file_name.replace(".groups.json", ".json"),
+        FileKind::League => file_name.replace(".json", ".groups.json"),
+        _ => return None,
+    };
+
+    Some(path.with_file_name(companion_name))
+}
+
+/// Competition codes are made of lower case letters and digits separated by dots, e.g. `en.1` or `at.cup`
+fn is_competition_code(code: &str) -> bool {
+    !code.is_empty()
+        && code.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.')
+        && code.starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+/// Codes of league files: national leagues and cups (`en.1`, `at.cup`, `br.1.apertura`) or
+/// international competitions (`worldcup`, `uefa.cl`)
+fn is_league_code(code: &str) -> bool {
+    if !is_competition_code(code) {
+        return false;
+    }
+
+    let mut parts = code.split('.');
+    let country = parts.next().unwrap_or_default();
+    if INTERNATIONAL_CODES.contains(&country) {
+        return true;
+    }
+
+    let is_country = (2..=3).contains(&country.len()) && country.chars().all(|c| c.is_ascii_lowercase());
+    let is_tier_or_cup = parts.next()
+        .is_some_and(|p| p == "cup" || (!p.is_empty() && p.chars().all(|c| c.is_ascii_digit())));
+
+    is_country && is_tier_or_cup
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind_of(file: &str) -> FileKind {
+        classify_file_name(Path::new(file))
+    }
+
+    #[test]
+    fn classifies_football_json_file_names() {
+        assert_eq!(kind_of("2020-21/en.1.clubs.json"), FileKind::Clubs);
+        assert_eq!(kind_of("2018--russia/worldcup.groups.json"), FileKind::Groups);
+        assert_eq!(kind_of("2020-21/en.1.json"), FileKind::League);
+        assert_eq!(kind_of("2020-21/at.cup.json"), FileKind::League);
+        assert_eq!(kind_of("2020-21/sco.1.json"), FileKind::League);
+        assert_eq!(kind_of("2020/br.1.apertura.json"), FileKind::League);
+        assert_eq!(kind_of("2018--russia/worldcup.json"), FileKind::League);
+        assert_eq!(kind_of("2020-21/uefa.cl.json"), FileKind::League);
+    }
+
+    #[test]
+    fn other_json_files_are_left_to_the_content() {
+        assert_eq!(kind_of("package.json"), FileKind::Unknown);
+        assert_eq!(kind_of("2020-21/stadiums.json"), FileKind::Unknown);
+        assert_eq!(kind_of("2020-21/en.json"), FileKind::Unknown);
+        assert_eq!(kind_of("2020-21/En.1.json"), FileKind::Unknown);
+        assert_eq!(kind_of("2020-21/en.1.txt"), FileKind::Unknown);
+    }
+
+    #[test]
+    fn classifies_content_by_its_top_level_keys() {
+        assert_eq!(classify_content(r#"{"name": "Clubs", "clubs": []}"#), FileKind::Clubs);
+        assert_eq!(classify_content(r#"{"name": "Groups", "groups": []}"#), FileKind::Groups);
+        assert_eq!(classify_content(r#"{"name": "League", "matches": []}"#), FileKind::League);
+        assert_eq!(classify_content(r#"{"name": "League", "rounds": []}"#), FileKind::League);
+        assert_eq!(classify_content(r#"{"name": "football-json", "version": "1.0.0"}"#), FileKind::Unknown);
+        assert_eq!(classify_content(r#"["matches"]"#), FileKind::Unknown);
+        assert_eq!(classify_content("not json"), FileKind::Unknown);
+    }
+
+    #[test]
+    fn league_and_groups_files_are_companions() {
+        assert_eq!(companion_file(Path::new("2018--russia/worldcup.json")), Some(PathBuf::from("2018--russia/worldcup.groups.json")));
+        assert_eq!(companion_file(Path::new("2018--russia/worldcup.groups.json")), Some(PathBuf::from("2018--russia/worldcup.json")));
+        assert_eq!(companion_file(Path::new("2020-21/en.1.clubs.json")), None);
+        assert_eq!(companion_file(Path::new("package.json")), None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+/// A season, parsed from the name of a football.json directory such as `2020-21` or `2018--russia`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Season {
+    /// Normalized name, e.g. `2020/21` or `2018`
+    pub name: String,
+    pub start_year: i32,
+    pub end_year: i32,
+}
+
+impl Season {
+    /// Parses directory names like `2020-21`, `2020-2021`, `2019` or `2018--russia`
+    pub fn from_dir_name(dir_name: &str) -> Option<Season> {
+        // Anything after a double dash (e.g. the host of a tournament) is not part of the season
+        let season_part = dir_name.split("--").next()?;
+        let mut parts = season_part.splitn(2, ['-', '_']);
+
+        let start_year = parse_year(parts.next()?, 4)?;
+        let end_year = match parts.next() {
+            None => start_year,
+            Some(end) if end.len() == 4 => parse_year(end, 4)?,
+            Some(end) if end.len() == 2 => {
+                let mut year = start_year / 100 * 100 + parse_year(end, 2)?;
+                if year < start_year {
+                    year += 100;
+                }
+                year
+            },
+            Some(_) => return None,
+        };
+
+        if end_year < start_year || end_year - start_year > 1 {
+            return None;
+        }
+
+        let name = if start_year == end_year {
+            start_year.to_string()
+        } else {
+            format!("{}/{:02}", start_year, end_year % 100)
+        };
+
+        Some(Season { name, start_year, end_year })
+    }
+
+    /// Finds the season of a file from the closest parent directory that is named after a season
+    pub fn from_path(path: &Path) -> Option<Season> {
+        path.ancestors()
+            .skip(1)
+            .filter_map(|p| p.file_name())
+            .find_map(|n| Season::from_dir_name(&n.to_string_lossy()))
+    }
+}
+
+fn parse_year(s: &str, digits: usize) -> Option<i32> {
+    if s.len() != digits || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+/// Returns the competition code a file belongs to, e.g. `en.1` for `en.1.json` or `en.1.clubs.json`
+pub fn competition_code(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    let stem = file_name.strip_suffix(".json")?;
+    let code = stem.strip_suffix(".clubs")
+        .or_else(|| stem.strip_suffix(".groups"))
+        .unwrap_or(stem);
+
+    if code.is_empty() {
+        return None;
+    }
+
+    Some(code.to_string())
+}
+
+/// Returns the tier of a national league from its code, e.g. `2` for `de.2`. Cups (`at.cup`)
+/// and international competitions have no tier.
+pub fn competition_tier(code: &str) -> Option<i32> {
+    let (_, tier) = code.split_once('.')?;
+    if !tier.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    tier.parse().ok().filter(|t| *t > 0)
+}
+
+/// Removes the season from a league name, e.g. `English Premier League 2020/21` -> `English Premier League`
+pub fn competition_name(league_name: &str) -> String {
+    let name = league_name.trim();
+
+    match name.rsplit_once(' ') {
+        Some((competition, last_word)) if is_season_like(last_word) => competition.trim().to_string(),
+        _ => name.to_string(),
+    }
+}
+
+fn is_season_like(word: &str) -> bool {
+    let mut parts = word.splitn(2, ['/', '-']);
+    let start = parts.next().unwrap_or_default();
+
+    parse_year(start, 4).is_some() && parts.next().is_none_or(|end| {
+        (end.len() == 2 || end.len() == 4) && end.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn season(name: &str, start_year: i32, end_year: i32) -> Option<Season> {
+        Some(Season { name: name.to_string(), start_year, end_year })
+    }
+
+    #[test]
+    fn parses_season_directories() {
+        assert_eq!(Season::from_dir_name("2020-21"), season("2020/21", 2020, 2021));
+        assert_eq!(Season::from_dir_name("2020-2021"), season("2020/21", 2020, 2021));
+        assert_eq!(Season::from_dir_name("2019"), season("2019", 2019, 2019));
+    }
+
+    #[test]
+    fn season_crossing_a_century_ends_in_the_next_one() {
+        assert_eq!(Season::from_dir_name("1999-00"), season("1999/00", 1999, 2000));
+    }
+
+    #[test]
+    fn host_of_a_tournament_is_not_part_of_the_season() {
+        assert_eq!(Season::from_dir_name("2018--russia"), season("2018", 2018, 2018));
+        assert_eq!(Season::from_dir_name("2020--europe"), season("2020", 2020, 2020));
+    }
+
+    #[test]
+    fn rejects_directories_that_are_not_seasons() {
+        assert_eq!(Season::from_dir_name("europe"), None);
+        assert_eq!(Season::from_dir_name("2020-23"), None);
+        assert_eq!(Season::from_dir_name("2021-20"), None);
+        assert_eq!(Season::from_dir_name("20-21"), None);
+    }
+
+    #[test]
+    fn season_of_a_file_comes_from_the_closest_season_directory() {
+        assert_eq!(Season::from_path(Path::new("europe/2018--russia/worldcup.json")), season("2018", 2018, 2018));
+        assert_eq!(Season::from_path(Path::new("2020-21/en.1.json")), season("2020/21", 2020, 2021));
+        assert_eq!(Season::from_path(Path::new("europe/cl.clubs.json")), None);
+    }
+
+    #[test]
+    fn competition_code_comes_from_the_file_name() {
+        assert_eq!(competition_code(Path::new("2020-21/en.1.json")), Some("en.1".to_string()));
+        assert_eq!(competition_code(Path::new("2020-21/en.1.clubs.json")), Some("en.1".to_string()));
+        assert_eq!(competition_code(Path::new("2018--russia/worldcup.groups.json")), Some("worldcup".to_string()));
+        assert_eq!(competition_code(Path::new("README.md")), None);
+    }
+
+    #[test]
+    fn competition_name_leaves_out_the_season() {
+        assert_eq!(competition_name("English Premier League 2020/21"), "English Premier League");
+        assert_eq!(competition_name("World Cup 2018"), "World Cup");
+        assert_eq!(competition_name("Copa América"), "Copa América");
+    }
+
+    #[test]
+    fn tier_comes_from_the_competition_code() {
+        assert_eq!(competition_tier("de.2"), Some(2));
+        assert_eq!(competition_tier("en.1"), Some(1));
+        assert_eq!(competition_tier("at.cup"), None);
+        assert_eq!(competition_tier("worldcup"), None);
+    }
+}
@@ -0,0 +1,110 @@
+/// A country or football association, as used in the prefix of football.json competition codes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Country {
+    /// Prefix used by football.json, e.g. `en` for England
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+/// Country codes used by football.json. These are mostly, but not always, ISO 3166 codes:
+/// the home nations of the UK have their own associations and codes (`en`, `sco`, `wa`, `ni`).
+const COUNTRIES: [Country; 48] = [
+    Country { code: "ar", name: "Argentina" },
+    Country { code: "at", name: "Austria" },
+    Country { code: "au", name: "Australia" },
+    Country { code: "be", name: "Belgium" },
+    Country { code: "bg", name: "Bulgaria" },
+    Country { code: "br", name: "Brazil" },
+    Country { code: "ca", name: "Canada" },
+    Country { code: "ch", name: "Switzerland" },
+    Country { code: "cl", name: "Chile" },
+    Country { code: "cn", name: "China" },
+    Country { code: "co", name: "Colombia" },
+    Country { code: "cy", name: "Cyprus" },
+    Country { code: "cz", name: "Czech Republic" },
+    Country { code: "de", name: "Germany" },
+    Country { code: "dk", name: "Denmark" },
+    Country { code: "en", name: "England" },
+    Country { code: "es", name: "Spain" },
+    Country { code: "fi", name: "Finland" },
+    Country { code: "fr", name: "France" },
+    Country { code: "gr", name: "Greece" },
+    Country { code: "hr", name: "Croatia" },
+    Country { code: "hu", name: "Hungary" },
+    Country { code: "ie", name: "Ireland" },
+    Country { code: "il", name: "Israel" },
+    Country { code: "is", name: "Iceland" },
+    Country { code: "it", name: "Italy" },
+    Country { code: "jp", name: "Japan" },
+    Country { code: "kr", name: "South Korea" },
+    Country { code: "lu", name: "Luxembourg" },
+    Country { code: "mx", name: "Mexico" },
+    Country { code: "ni", name: "Northern Ireland" },
+    Country { code: "nl", name: "Netherlands" },
+    Country { code: "no", name: "Norway" },
+    Country { code: "pl", name: "Poland" },
+    Country { code: "pt", name: "Portugal" },
+    Country { code: "py", name: "Paraguay" },
+    Country { code: "ro", name: "Romania" },
+    Country { code: "rs", name: "Serbia" },
+    Country { code: "ru", name: "Russia" },
+    Country { code: "sa", name: "Saudi Arabia" },
+    Country { code: "sco", name: "Scotland" },
+    Country { code: "se", name: "Sweden" },
+    Country { code: "si", name: "Slovenia" },
+    Country { code: "tr", name: "Turkey" },
+    Country { code: "ua", name: "Ukraine" },
+    Country { code: "us", name: "United States" },
+    Country { code: "uy", name: "Uruguay" },
+    Country { code: "wa", name: "Wales" },
+];
+
+/// Returns the country a competition belongs to, from the prefix of its code, e.g. `en` for `en.1`.
+/// International competitions such as `cl` or `worldcup` have a single part code and no country.
+pub fn country_from_competition_code(competition_code: &str) -> Option<Country> {
+    let (prefix, _) = competition_code.split_once('.')?;
+    country_from_code(prefix)
+}
+
+/// Looks up a country by its football.json code
+pub fn country_from_code(code: &str) -> Option<Country> {
+    COUNTRIES.iter().find(|c| c.code == code).copied()
+}
+
+/// Looks up a country by its English name, ignoring case, e.g. the `country` of a club
+pub fn country_from_name(name: &str) -> Option<Country> {
+    COUNTRIES.iter().find(|c| c.name.eq_ignore_ascii_case(name.trim())).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn country_name(competition_code: &str) -> Option<&'static str> {
+        country_from_competition_code(competition_code).map(|c| c.name)
+    }
+
+    #[test]
+    fn countries_come_from_football_json_competition_codes() {
+        assert_eq!(country_name("en.1"), Some("England"));
+        assert_eq!(country_name("sco.1"), Some("Scotland"));
+        assert_eq!(country_name("de.2"), Some("Germany"));
+        assert_eq!(country_name("at.cup"), Some("Austria"));
+        assert_eq!(country_name("br.1"), Some("Brazil"));
+        assert_eq!(country_name("mx.1"), Some("Mexico"));
+    }
+
+    #[test]
+    fn international_competitions_have_no_country() {
+        assert_eq!(country_name("worldcup"), None);
+        assert_eq!(country_name("cl"), None);
+        assert_eq!(country_name("uefa.cl"), None);
+    }
+
+    #[test]
+    fn country_codes_are_unique() {
+        for (i, country) in COUNTRIES.iter().enumerate() {
+            assert!(COUNTRIES[i + 1..].iter().all(|c| c.code != country.code && c.name != country.name), "{:?}", country);
+        }
+    }
+}
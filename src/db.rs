@@ -1,98 +1,726 @@
+use std::{path::Path, cell::Cell, collections::{HashMap, HashSet}, sync::Arc};
+
 use log::{warn, info};
-use rusqlite::{Connection, Result, Error, params};
+use chrono::NaiveDate;
+use rusqlite::{Connection, Result, Error, OptionalExtension, Params, params};
+
+use crate::{json::{JsonLoader, TeamResolution}, stream::LeagueInfo, model::{Goal, GroupList, LeagueMatch, OldLeagueMatch, Team, TeamOrigin}, competition::{competition_code, competition_name, competition_tier, Season}, country::{country_from_competition_code, country_from_name, Country}, report::{IngestError, IngestReport, UpsertOutcome}, migration::{migrate, MigrationError}, rating::{rate_matches, EloSettings, RatedMatch}, standings::{compute_standings, standings_after_round, standings_at_date, standings_by_round, MatchResult, StandingsRow, StandingsRules}};
 
-use crate::{json::JsonLoader, model::{LeagueJsonFormat, LeagueMatch}};
+/// Metadata key holding the football.json commit the database was built from
+pub const SOURCE_COMMIT_KEY: &str = "source_commit";
+/// Metadata key holding the URL football.json was fetched from
+pub const SOURCE_URL_KEY: &str = "source_url";
+/// Metadata key holding the points for a win, draw and loss the standings were computed with
+pub const STANDINGS_POINTS_KEY: &str = "standings_points";
+/// Metadata key holding the tie-breakers the standings were computed with
+pub const STANDINGS_TIE_BREAKERS_KEY: &str = "standings_tie_breakers";
+/// Metadata key holding the settings the Elo ratings were computed with
+pub const ELO_SETTINGS_KEY: &str = "elo_settings";
+
+/// Number of statements kept prepared. Covers every statement used while inserting a load.
+const STATEMENT_CACHE_CAPACITY: usize = 48;
 
 pub struct DatabaseProcessor {
     conn: Option<Connection>,
+    /// Rows written before the transaction is committed. 0 commits the whole load at once.
+    batch_size: usize,
+    /// Rows written since the last commit
+    pending_rows: Cell<usize>,
     initialized: bool,
 }
 
+impl Default for DatabaseProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DatabaseProcessor {
     pub fn new() -> Self {
-        DatabaseProcessor { conn: None, initialized: false }
+        DatabaseProcessor { conn: None, batch_size: 0, pending_rows: Cell::new(0), initialized: false }
     }
 
-    pub fn init(&mut self, path: &str) -> Result<()> {
-        self.conn = Some(Connection::open(path)?);
+    /// Opens the database and brings its schema up to date. Databases from a newer version are refused.
+    pub fn init(&mut self, path: &str) -> std::result::Result<(), MigrationError> {
+        let mut conn = Connection::open(path)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        // Rows referring to missing teams, leagues etc. are rejected rather than written
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
         
-        self.create_teams_table()?;
-        self.create_leagues_table()?;
-        self.create_matches_table()?;
+        // Databases written by older versions of the loader were not checked
+        let violations: usize = conn.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", (), |row| row.get(0))?;
+        if violations > 0 {
+            warn!("The database has {} row(s) referring to missing rows. Rebuilding it from scratch is recommended", violations);
+        }
+        self.conn = Some(conn);
         
         self.initialized = true;
 
         Ok(())
     }
     
-    fn create_teams_table(&self) -> Result<()> {
-        if let Some(conn) = &self.conn {
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS teams (
-                    id      INTEGER PRIMARY KEY,
-                    name    TEXT    NOT NULL,
-                    code    TEXT
-                )", 
-                ()
-            )?;
+    /// Stores a key-value pair in the metadata table, replacing any previous value
+    pub fn set_metadata(&self, key: &str, value: &str) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value)
+            VALUES (?1, ?2)",
+            params![key, value]
+        )?;
+        
+        Ok(())
+    }
+    
+    /// Reads a value from the metadata table
+    pub fn get_metadata(&self, key: &str) -> Result<Option<String>> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        conn.query_row(
+            "SELECT value FROM metadata WHERE key = ?1",
+            params![key],
+            |row| row.get(0)
+        ).optional()
+    }
+    
+    /// Sets after how many rows a load is committed. With 0 (the default), the whole load is committed
+    /// at once, so a load that fails leaves the database untouched. Larger loads can use batches to keep
+    /// the journal small, at the cost of a failed load only rolling back the batch in progress.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+    
+    /// Starts the transaction all following changes are made in, until commit_transaction or rollback_transaction is called
+    pub fn begin_transaction(&self) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        self.pending_rows.set(0);
+        conn.execute_batch("BEGIN")
+    }
+    
+    pub fn commit_transaction(&self) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        conn.execute_batch("COMMIT")
+    }
+    
+    /// Undoes every change since the transaction (or the current batch) was started
+    pub fn rollback_transaction(&self) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        if conn.is_autocommit() {
+            return Ok(());
+        }
+        
+        conn.execute_batch("ROLLBACK")
+    }
+    
+    /// Counts a written row and commits the batch once it is full
+    fn row_written(&self, conn: &Connection) -> Result<()> {
+        if self.batch_size == 0 || conn.is_autocommit() {
+            return Ok(());
+        }
+        
+        let pending_rows = self.pending_rows.get() + 1;
+        if pending_rows < self.batch_size {
+            self.pending_rows.set(pending_rows);
+            return Ok(());
+        }
+        
+        info!("Committing a batch of {} rows", pending_rows);
+        conn.execute_batch("COMMIT; BEGIN")?;
+        self.pending_rows.set(0);
+        
+        Ok(())
+    }
+    
+    /// Inserts all data from a JsonLoader object into the SQLite database, reading the league files one at a time
+    /// through JsonLoader::stream_leagues. Teams are resolved as their matches are inserted, following the missing
+    /// team policy. load_teams and load_groups have to be called first. Returns what was inserted and every
+    /// record that had to be skipped. Unless begin_transaction was called before, everything is inserted in a
+    /// transaction of its own, which is committed at the end or rolled back if the insert fails.
+    pub fn insert_data_streaming(&self, json_loader: &mut JsonLoader) -> Result<IngestReport> {
+        if !self.initialized {
+            warn!("DatabaseProcessor not yet initialized. Please initialize this object first.");
+            return Err(Error::InvalidQuery);
+        }
+        
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        if !conn.is_autocommit() {
+            return self.insert_streamed_data(conn, json_loader);
+        }
+        
+        self.begin_transaction()?;
+        let result = self.insert_streamed_data(conn, json_loader);
+        match result {
+            Ok(_) => self.commit_transaction()?,
+            Err(_) => {
+                if let Err(e) = self.rollback_transaction() {
+                    warn!("Failed to roll back the insert: {}", e);
+                }
+            },
+        };
+        
+        result
+    }
+    
+    /// Does the work of insert_data_streaming within the current transaction
+    fn insert_streamed_data(&self, conn: &Connection, json_loader: &mut JsonLoader) -> Result<IngestReport> {
+        let mut report = IngestReport::new();
+        self.insert_teams_from_loader(json_loader, &mut report)?;
+        
+        info!("Inserting Team Aliases...");
+        self.insert_aliases_from_loader(json_loader, 0, &mut report)?;
+        let mut inserted_aliases = json_loader.get_aliases().len();
+        
+        info!("Inserting leagues and matches...");
+        // ID the loader gave a league -> ID it is stored with, which differs if the league was already stored
+        let mut stored_league_ids: HashMap<i32, i32> = HashMap::new();
+        for league in json_loader.stream_leagues() {
+            let league = match league {
+                Ok(l) => l,
+                Err(entry) => {
+                    report.record(entry.file, entry.item, entry.error);
+                    continue;
+                }
+            };
+            report.files_loaded += 1;
+            
+            let info = match self.insert_league(conn, &league.league, &mut report)? {
+                Some(id) if id == league.league.id => league.league.clone(),
+                Some(id) => Arc::new(LeagueInfo { id, ..(*league.league).clone() }),
+                None => continue,
+            };
+            stored_league_ids.insert(league.league.id, info.id);
+            
+            for record in league.matches.iter() {
+                for name in [&record.match_info.team1, &record.match_info.team2] {
+                    self.resolve_team(conn, json_loader, name, &info.source_file, &mut inserted_aliases, &mut report)?;
+                }
+                
+                self.insert_match(conn, json_loader, &mut report, &info, record.stage.as_deref(), &record.round, &record.match_info)?;
+            }
+        }
+        
+        let group_league_ids = json_loader.get_groups().iter()
+            .map(|g| self.find_group_league(conn, g, &stored_league_ids))
+            .collect::<Result<Vec<Option<i32>>>>()?;
+        
+        // Only members of groups that are going to be inserted are resolved, so no teams are created for skipped groups
+        let group_teams: Vec<(String, String)> = json_loader.get_groups().iter()
+            .zip(group_league_ids.iter())
+            .filter(|(_, league_id)| league_id.is_some())
+            .flat_map(|(g, _)| {
+                g.groups.iter()
+                    .flat_map(|group| group.teams.iter())
+                    .map(move |t| (t.get_name().to_string(), g.source_file.clone()))
+            })
+            .collect();
+        for (name, source_file) in group_teams.iter() {
+            self.resolve_team(conn, json_loader, name, source_file, &mut inserted_aliases, &mut report)?;
+        }
+        self.insert_groups_from_loader(json_loader, &group_league_ids, &mut report)?;
+        
+        Ok(report)
+    }
+    
+    /// Finds the stored league of a groups file: the league from the league file next to it, or else
+    /// the league with the same name as the groups file
+    fn find_group_league(&self, conn: &Connection, group_list: &GroupList, stored_league_ids: &HashMap<i32, i32>) -> Result<Option<i32>> {
+        if let Some(id) = group_list.league_id.and_then(|id| stored_league_ids.get(&id)) {
+            return Ok(Some(*id));
+        }
+        
+        conn.prepare_cached("SELECT id FROM leagues WHERE name = ?1 ORDER BY id")?
+            .query_row(params![group_list.name], |row| row.get(0))
+            .optional()
+    }
+    
+    /// Resolves a team name while streaming, and inserts the team or alias it was resolved to if it is new
+    fn resolve_team(&self, conn: &Connection, json_loader: &mut JsonLoader, name: &str, source_file: &str, inserted_aliases: &mut usize, report: &mut IngestReport) -> Result<()> {
+        if let TeamResolution::Created(team) = json_loader.resolve_team_name(name, source_file) {
+            self.insert_team(conn, &team, report)?;
+        }
+        
+        if json_loader.get_aliases().len() > *inserted_aliases {
+            self.insert_aliases_from_loader(json_loader, *inserted_aliases, report)?;
+            *inserted_aliases = json_loader.get_aliases().len();
+        }
+        
+        Ok(())
+    }
+    
+    /// Makes the teams, league IDs and group IDs already stored in the database known to the JsonLoader,
+    /// so that a load into an existing database refers to existing teams and does not reuse IDs.
+    pub fn prepare_incremental_load(&self, json_loader: &mut JsonLoader) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        let mut stmt = conn.prepare(
+            "SELECT teams.id, teams.name, teams.code, teams.source_file, teams.origin, countries.name
+            FROM teams
+            LEFT JOIN countries ON countries.id = teams.country_id"
+        )?;
+        let teams = stmt.query_map((), |row| {
+            Ok(Team {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                code: row.get(2)?,
+                country: row.get(5)?,
+                source_file: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                origin: TeamOrigin::from_name(&row.get::<_, String>(4)?).unwrap_or_default(),
+            })
+        })?;
+        
+        for team in teams {
+            json_loader.register_team(team?);
+        }
+        
+        let mut stmt = conn.prepare("SELECT alias, team_id FROM team_aliases")?;
+        let aliases = stmt.query_map((), |row| Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?)))?;
+        
+        for alias in aliases {
+            let (alias, team_id) = alias?;
+            json_loader.register_alias(&alias, team_id);
+        }
+        
+        let mut stmt = conn.prepare("SELECT id, source_file FROM leagues")?;
+        let leagues = stmt.query_map((), |row| Ok((row.get::<_, i32>(0)?, row.get::<_, Option<String>>(1)?)))?;
+        
+        for league in leagues {
+            let (id, source_file) = league?;
+            json_loader.reserve_league_id(id, &source_file.unwrap_or_default());
+        }
+        
+        let mut stmt = conn.prepare("SELECT id, source_file, name FROM groups")?;
+        let groups = stmt.query_map((), |row| {
+            Ok((row.get::<_, i32>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, String>(2)?))
+        })?;
+        
+        for group in groups {
+            let (id, source_file, name) = group?;
+            json_loader.reserve_group_id(id, &source_file.unwrap_or_default(), &name);
         }
         
         Ok(())
     }
     
-    fn create_leagues_table(&self) -> Result<()> {
-        if let Some(conn) = &self.conn {
+    /// Returns the groups files whose groups belong to a league loaded from one of the given files, other than
+    /// the given files themselves. These are groups files linked to their league by name.
+    pub fn get_linked_groups_files(&self, files: &[String]) -> Result<Vec<String>> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        let mut linked_files: Vec<String> = Vec::new();
+        let mut stmt = conn.prepare_cached(
+            "SELECT DISTINCT groups.source_file FROM groups
+            JOIN leagues ON leagues.id = groups.league_id
+            WHERE leagues.source_file = ?1 AND groups.source_file IS NOT NULL"
+        )?;
+        
+        for file in files {
+            for linked_file in stmt.query_map(params![file], |row| row.get::<_, String>(0))? {
+                let linked_file = linked_file?;
+                if !files.contains(&linked_file) && !linked_files.contains(&linked_file) {
+                    linked_files.push(linked_file);
+                }
+            }
+        }
+        
+        linked_files.sort();
+        Ok(linked_files)
+    }
+    
+    /// Deletes the leagues loaded from the given files along with their matches and groups.
+    /// Groups loaded from the given files are deleted as well.
+    pub fn remove_leagues_from_files(&self, files: &[String]) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        for file in files {
+            conn.execute(
+                "DELETE FROM standings
+                WHERE league_id IN (SELECT id FROM leagues WHERE source_file = ?1)
+                OR group_id IN (SELECT id FROM groups WHERE source_file = ?1)",
+                params![file]
+            )?;
+            
+            conn.execute(
+                "DELETE FROM standings_history
+                WHERE league_id IN (SELECT id FROM leagues WHERE source_file = ?1)
+                OR group_id IN (SELECT id FROM groups WHERE source_file = ?1)",
+                params![file]
+            )?;
+            
             conn.execute(
-                "CREATE TABLE IF NOT EXISTS leagues (
-                    id      INTEGER PRIMARY KEY,
-                    name    TEXT    NOT NULL
+                "DELETE FROM group_teams WHERE group_id IN (
+                    SELECT id FROM groups
+                    WHERE source_file = ?1
+                    OR league_id IN (SELECT id FROM leagues WHERE source_file = ?1)
                 )",
-                ()
+                params![file]
             )?;
             
+            conn.execute(
+                "DELETE FROM groups
+                WHERE source_file = ?1
+                OR league_id IN (SELECT id FROM leagues WHERE source_file = ?1)",
+                params![file]
+            )?;
+            
+            conn.execute(
+                "DELETE FROM team_ratings WHERE match_id IN (
+                    SELECT matches.id FROM matches
+                    JOIN leagues ON leagues.id = matches.league_id
+                    WHERE leagues.source_file = ?1
+                )",
+                params![file]
+            )?;
+            
+            conn.execute(
+                "DELETE FROM goals WHERE match_id IN (
+                    SELECT matches.id FROM matches
+                    JOIN leagues ON leagues.id = matches.league_id
+                    WHERE leagues.source_file = ?1
+                )",
+                params![file]
+            )?;
+            
+            conn.execute(
+                "DELETE FROM matches WHERE league_id IN (SELECT id FROM leagues WHERE source_file = ?1)",
+                params![file]
+            )?;
+            
+            let removed = conn.execute("DELETE FROM leagues WHERE source_file = ?1", params![file])?;
+            if removed > 0 {
+                info!("Removed {} league(s) loaded from: {}", removed, file);
+            }
         }
         
+        // Players and venues only exist through goals and matches
+        conn.execute("DELETE FROM players WHERE id NOT IN (SELECT player_id FROM goals)", ())?;
+        conn.execute("DELETE FROM venues WHERE id NOT IN (SELECT venue_id FROM matches WHERE venue_id IS NOT NULL)", ())?;
+        
         Ok(())
     }
     
-    fn create_matches_table(&self) -> Result<()> {
-        if let Some(conn) = &self.conn {
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS matches (
-                    id          INTEGER PRIMARY KEY,
-                    date        TEXT NOT NULL,
-                    league_id   INTEGER NOT NULL REFERENCES leagues(id),
-                    team1_id    INTEGER NOT NULL REFERENCES teams(id),
-                    team2_id    INTEGER NOT NULL REFERENCES teams(id),
-                    stage       TEXT,
-                    round       TEXT,
-                    team1_score INTEGER,
-                    team2_score INTEGER
-                )",
-                ()
+    /// Deletes the teams whose source file is one of the given files. Teams declared in another loaded file as well
+    /// have that file as their source file by now. Teams still referenced by a match or group are kept.
+    pub fn remove_teams_from_files(&self, files: &[String]) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        // Teams still referenced by a match, group or player are kept
+        let unused_teams = "SELECT id FROM teams
+            WHERE source_file = ?1
+            AND id NOT IN (
+                SELECT team1_id FROM matches
+                UNION SELECT team2_id FROM matches
+                UNION SELECT team_id FROM group_teams
+                UNION SELECT team_id FROM players
+                UNION SELECT team_id FROM standings
+                UNION SELECT team_id FROM standings_history
+                UNION SELECT team_id FROM team_ratings
+            )";
+        
+        for file in files {
+            // Aliases refer to their team, so they have to go first
+            conn.execute(&format!("DELETE FROM team_aliases WHERE team_id IN ({})", unused_teams), params![file])?;
+            let removed = conn.execute(&format!("DELETE FROM teams WHERE id IN ({})", unused_teams), params![file])?;
+            
+            if removed > 0 {
+                info!("Removed {} team(s) declared in: {}", removed, file);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Returns the results of the matches of a league that have a score, in the order they were played
+    pub fn get_league_results(&self, league_id: i32) -> Result<Vec<MatchResult>> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        let mut stmt = conn.prepare_cached(
+            "SELECT date, round, stage, team1_id, team2_id, team1_score, team2_score
+            FROM matches
+            WHERE league_id = ?1 AND team1_score IS NOT NULL AND team2_score IS NOT NULL
+            ORDER BY date, id"
+        )?;
+        let results = stmt.query_map(params![league_id], |row| {
+            Ok(MatchResult {
+                date: row.get(0)?,
+                round: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                stage: row.get(2)?,
+                team1_id: row.get(3)?,
+                team2_id: row.get(4)?,
+                team1_score: row.get(5)?,
+                team2_score: row.get(6)?,
+            })
+        })?;
+        
+        results.collect()
+    }
+    
+    /// Computes the table of a league from the matches in the database
+    pub fn get_league_standings(&self, league_id: i32, rules: &StandingsRules) -> Result<Vec<StandingsRow>> {
+        Ok(compute_standings(&self.get_league_results(league_id)?, rules))
+    }
+    
+    /// Computes the table of a league as it was at the end of a date
+    pub fn get_league_standings_at_date(&self, league_id: i32, date: NaiveDate, rules: &StandingsRules) -> Result<Vec<StandingsRow>> {
+        Ok(standings_at_date(&self.get_league_results(league_id)?, date, rules))
+    }
+    
+    /// Computes the table of a league after a round, e.g. after matchday 10
+    pub fn get_league_standings_after_round(&self, league_id: i32, matchday: i32, rules: &StandingsRules) -> Result<Vec<StandingsRow>> {
+        Ok(standings_after_round(&self.get_league_results(league_id)?, matchday, rules))
+    }
+    
+    /// Replaces the contents of the standings table with the table of every league, and of every group of a
+    /// league, and the contents of the standings_history table with the same tables after every round.
+    /// A group table counts the group stage matches between the members of the group. The rules used are recorded
+    /// in the metadata table.
+    pub fn write_standings(&self, rules: &StandingsRules) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        info!("Computing standings...");
+        conn.execute("DELETE FROM standings", ())?;
+        conn.execute("DELETE FROM standings_history", ())?;
+        
+        let league_ids = conn.prepare("SELECT id FROM leagues ORDER BY id")?
+            .query_map((), |row| row.get::<_, i32>(0))?
+            .collect::<Result<Vec<i32>>>()?;
+        
+        for league_id in league_ids {
+            let results = self.get_league_results(league_id)?;
+            self.insert_standings(conn, league_id, None, &results, rules)?;
+            
+            let groups = conn.prepare_cached("SELECT id, name FROM groups WHERE league_id = ?1 ORDER BY id")?
+                .query_map(params![league_id], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<(i32, String)>>>()?;
+            
+            for (group_id, group_name) in groups {
+                let members = conn.prepare_cached("SELECT team_id FROM group_teams WHERE group_id = ?1")?
+                    .query_map(params![group_id], |row| row.get::<_, i32>(0))?
+                    .collect::<Result<HashSet<i32>>>()?;
+                
+                // Members of a group can meet again in the knockout stage, which doesn't count for the group
+                let group_results: Vec<MatchResult> = results.iter()
+                    .filter(|r| members.contains(&r.team1_id) && members.contains(&r.team2_id))
+                    .filter(|r| is_group_stage(r.stage.as_deref(), &group_name))
+                    .cloned()
+                    .collect();
+                self.insert_standings(conn, league_id, Some(group_id), &group_results, rules)?;
+            }
+        }
+        
+        self.set_metadata(STANDINGS_POINTS_KEY, &rules.points.to_string())?;
+        self.set_metadata(STANDINGS_TIE_BREAKERS_KEY, &rules.tie_breakers_string())?;
+        
+        Ok(())
+    }
+    
+    /// Replaces the contents of the team_ratings table with the Elo ratings of every team before and after each
+    /// of its matches, rating all matches with a score in the order they were played. The settings used are
+    /// recorded in the metadata table.
+    pub fn write_ratings(&self, settings: EloSettings) -> Result<()> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        info!("Computing ratings...");
+        conn.execute("DELETE FROM team_ratings", ())?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT matches.id, matches.date, seasons.start_year, matches.team1_id, matches.team2_id,
+                matches.team1_score, matches.team2_score
+            FROM matches
+            JOIN leagues ON leagues.id = matches.league_id
+            LEFT JOIN seasons ON seasons.id = leagues.season_id
+            WHERE matches.team1_score IS NOT NULL AND matches.team2_score IS NOT NULL"
+        )?;
+        let matches = stmt.query_map((), |row| {
+            Ok(RatedMatch {
+                match_id: row.get(0)?,
+                date: row.get(1)?,
+                season_start_year: row.get(2)?,
+                team1_id: row.get(3)?,
+                team2_id: row.get(4)?,
+                team1_score: row.get(5)?,
+                team2_score: row.get(6)?,
+            })
+        })?.collect::<Result<Vec<RatedMatch>>>()?;
+        
+        for entry in rate_matches(&matches, settings).history() {
+            execute_cached(conn, 
+                "INSERT INTO team_ratings (team_id, match_id, date, rating_before, rating)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![entry.team_id, entry.match_id, entry.date, entry.rating_before, entry.rating]
             )?;
+            self.row_written(conn)?;
         }
         
+        self.set_metadata(ELO_SETTINGS_KEY, &settings.to_string())?;
+        
         Ok(())
     }
     
-    /// Inserts all data from a JsonLoader object into the SQLite database
-    pub fn insert_data_from_loader(&self, json_loader: &JsonLoader) -> Result<()> {
-        if !self.initialized {
-            warn!("DatabaseProcessor not yet initialized. Please initialize this object first.");
-            return Err(Error::InvalidQuery);
+    /// Returns the Elo rating of a team at the end of a date, or None if it hadn't played a rated match by then
+    pub fn get_team_rating_at(&self, team_id: i32, date: NaiveDate) -> Result<Option<f64>> {
+        let conn = match &self.conn {
+            Some(c) => c,
+            None => {
+                warn!("Connection not established. Please initialize the object first.");
+                return Err(Error::InvalidQuery);
+            }
+        };
+        
+        conn.query_row(
+            "SELECT rating FROM team_ratings
+            WHERE team_id = ?1 AND date <= ?2
+            ORDER BY date DESC, match_id DESC
+            LIMIT 1",
+            params![team_id, date],
+            |row| row.get(0)
+        ).optional()
+    }
+    
+    /// Inserts the final table of a league or group along with the tables after every round
+    fn insert_standings(&self, conn: &Connection, league_id: i32, group_id: Option<i32>, results: &[MatchResult], rules: &StandingsRules) -> Result<()> {
+        for row in compute_standings(results, rules) {
+            execute_cached(conn, 
+                "INSERT INTO standings (
+                    league_id, group_id, team_id, position, played, won, drawn, lost,
+                    goals_for, goals_against, goal_difference, points
+                )
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    league_id,
+                    group_id,
+                    row.team_id,
+                    row.position,
+                    row.played,
+                    row.won,
+                    row.drawn,
+                    row.lost,
+                    row.goals_for,
+                    row.goals_against,
+                    row.goal_difference(),
+                    row.points
+                ]
+            )?;
+            self.row_written(conn)?;
+        }
+        
+        for round in standings_by_round(results, rules) {
+            for row in round.rows {
+                execute_cached(conn, 
+                    "INSERT INTO standings_history (
+                        league_id, group_id, matchday, round, team_id, position, played, won, drawn, lost,
+                        goals_for, goals_against, goal_difference, points
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    params![
+                        league_id,
+                        group_id,
+                        round.matchday,
+                        round.round,
+                        row.team_id,
+                        row.position,
+                        row.played,
+                        row.won,
+                        row.drawn,
+                        row.lost,
+                        row.goals_for,
+                        row.goals_against,
+                        row.goal_difference(),
+                        row.points
+                    ]
+                )?;
+                self.row_written(conn)?;
+            }
         }
-
-        self.insert_teams_from_loader(json_loader)?;
-        self.insert_leagues_from_loader(json_loader)?;
-        self.insert_matches_from_loader(json_loader)?;
         
         Ok(())
     }
     
     /// Iterates through all teams loaded into the JsonLoader and inserts them
     /// into the SQLite database
-    fn insert_teams_from_loader(&self, json_loader: &JsonLoader) -> Result<()> {
+    fn insert_teams_from_loader(&self, json_loader: &JsonLoader, report: &mut IngestReport) -> Result<()> {
         info!("Inserting Teams...");
         let team_list = json_loader.get_teams();
         let conn = match &self.conn {
@@ -104,30 +732,63 @@ impl DatabaseProcessor {
         };
         
         for team in team_list.iter() {
-            let insert_result = conn.execute(
-                "INSERT INTO teams (id, name, code)
-                VALUES (?1, ?2, ?3)",
-                params![
-                    team.id,
-                    team.name,
-                    team.code
-                ]
-            );
-            
-            if let Err(e) = insert_result {
-                warn!("Failed to insert team: {}", e.to_string());
-                warn!("Data: {} {} {}", team.id.to_string(), team.name, team.code.clone().unwrap_or("NONE".to_string()));
-                continue;
-            }
+            self.insert_team(conn, team, report)?;
         }
 
         Ok(())
     }
     
-    /// Iterates through all leagues and inserts them into the SQLite database.
-    fn insert_leagues_from_loader(&self, json_loader: &JsonLoader) -> Result<()> {
-        info!("Inserting Leagues...");
-        let leagues = json_loader.get_leagues();
+    /// Inserts or updates a single team. Only fails if the batch could not be committed.
+    fn insert_team(&self, conn: &Connection, team: &Team, report: &mut IngestReport) -> Result<()> {
+        // Clubs of national leagues belong to the country of the league, clubs of international
+        // competitions to the country given in the clubs file
+        let country = competition_code(Path::new(&team.source_file))
+            .and_then(|code| country_from_competition_code(&code))
+            .or_else(|| team.country.as_deref().and_then(country_from_name));
+        
+        let country_id = match country {
+            Some(c) => Some(self.get_or_insert_country(conn, &c)?),
+            None => None,
+        };
+        
+        let existing_id = find_id(conn, "SELECT id FROM teams WHERE name = ?1", params![team.name])?;
+        
+        // Teams are matched on their name, keep their stored ID and are only written if something changed
+        let upsert_result = execute_cached(conn, 
+            "INSERT INTO teams (id, name, code, country_id, origin, source_file)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (name) DO UPDATE SET code = excluded.code, country_id = excluded.country_id,
+                origin = excluded.origin, source_file = excluded.source_file
+            WHERE (code, country_id, origin, source_file)
+                IS NOT (excluded.code, excluded.country_id, excluded.origin, excluded.source_file)",
+            params![
+                team.id,
+                team.name,
+                team.code,
+                country_id,
+                team.origin.as_str(),
+                team.source_file
+            ]
+        );
+        
+        let changed = match upsert_result {
+            Ok(c) => c,
+            Err(e) => {
+                report.record(Some(team.source_file.clone()), Some(format!("team {}", team.name)), insert_failed("teams", e));
+                return Ok(());
+            }
+        };
+        report.teams.count(upsert_outcome(existing_id.is_some(), changed));
+        
+        // A declared team is no alias, even if an earlier load stored its name as one
+        execute_cached(conn, "DELETE FROM team_aliases WHERE alias = ?1", params![team.name])?;
+        
+        self.row_written(conn)
+    }
+    
+    /// Iterates through all aliases resolved by the JsonLoader and inserts them into the SQLite database.
+    /// The first `skip` aliases are left out, as they have already been inserted.
+    fn insert_aliases_from_loader(&self, json_loader: &JsonLoader, skip: usize, report: &mut IngestReport) -> Result<()> {
         let conn = match &self.conn {
             Some(c) => c,
             None => {
@@ -136,27 +797,321 @@ impl DatabaseProcessor {
             }
         };
         
-        for league in leagues.iter() {
-            let insert_res = conn.execute(
-                "INSERT INTO leagues (name)
-                VALUES (?1)",
-                &[&league.get_league_name()]
+        for (alias, team, source) in json_loader.get_aliases().iter().skip(skip) {
+            let insert_res = execute_cached(conn, 
+                "INSERT INTO team_aliases (alias, team_id, source)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (alias) DO UPDATE SET team_id = excluded.team_id, source = excluded.source",
+                params![alias, team.id, source.as_str()]
             );
             
             if let Err(e) = insert_res {
-                warn!("Failed to insert league: {}", e.to_string());
-                warn!("Data: {}", league.get_league_name());
+                report.record(None, Some(format!("alias {} of {}", alias, team.name)), insert_failed("team_aliases", e));
                 continue;
             }
+            self.row_written(conn)?;
         }
-
+        
+        Ok(())
+    }
+    
+    /// Inserts or updates a single league, along with its competition and season. A league is the same as an
+    /// existing one if it has the same competition and season, or the same ID. Returns the ID of the league in
+    /// the database, or None if it could not be written.
+    fn insert_league(&self, conn: &Connection, league: &LeagueInfo, report: &mut IngestReport) -> Result<Option<i32>> {
+        let competition_id = match &league.competition_code {
+            Some(code) => {
+                let country_id = match country_from_competition_code(code) {
+                    Some(c) => Some(self.get_or_insert_country(conn, &c)?),
+                    None => None,
+                };
+                
+                Some(self.get_or_insert_competition(conn, code, &competition_name(&league.name), country_id)?)
+            },
+            None => None,
+        };
+        
+        let season_id = match &league.season {
+            Some(season) => Some(self.get_or_insert_season(conn, season)?),
+            None => None,
+        };
+        
+        // Another file may hold the same season of the competition, in which case its league is updated
+        let existing_id = find_id(conn, 
+            "SELECT id FROM leagues
+            WHERE (competition_id = ?1 AND season_id = ?2) OR id = ?3
+            ORDER BY id = ?3
+            LIMIT 1",
+            params![competition_id, season_id, league.id]
+        )?;
+        
+        let upsert_res = execute_cached(conn, 
+            "INSERT INTO leagues (id, name, competition_id, season_id, source_file)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT DO UPDATE SET name = excluded.name, competition_id = excluded.competition_id,
+                season_id = excluded.season_id, source_file = excluded.source_file
+            WHERE (name, competition_id, season_id, source_file)
+                IS NOT (excluded.name, excluded.competition_id, excluded.season_id, excluded.source_file)",
+            params![
+                league.id,
+                league.name,
+                competition_id,
+                season_id,
+                league.source_file
+            ]
+        );
+        
+        let changed = match upsert_res {
+            Ok(c) => c,
+            Err(e) => {
+                report.record(Some(league.source_file.clone()), None, insert_failed("leagues", e));
+                return Ok(None);
+            }
+        };
+        report.leagues.count(upsert_outcome(existing_id.is_some(), changed));
+        self.row_written(conn)?;
+        
+        Ok(Some(existing_id.map_or(league.id, |id| id as i32)))
+    }
+    
+    /// Returns the ID of the competition with the given code, creating it if needed
+    fn get_or_insert_competition(&self, conn: &Connection, code: &str, name: &str, country_id: Option<i32>) -> Result<i32> {
+        execute_cached(conn, 
+            "INSERT INTO competitions (code, name, country_id, tier)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (code) DO NOTHING",
+            params![code, name, country_id, competition_tier(code)]
+        )?;
+        
+        conn.prepare_cached("SELECT id FROM competitions WHERE code = ?1")?.query_row(params![code], |row| row.get(0))
+    }
+    
+    /// Returns the ID of the given country, creating it if needed
+    fn get_or_insert_country(&self, conn: &Connection, country: &Country) -> Result<i32> {
+        execute_cached(conn, 
+            "INSERT INTO countries (code, name)
+            VALUES (?1, ?2)
+            ON CONFLICT (code) DO NOTHING",
+            params![country.code, country.name]
+        )?;
+        
+        conn.prepare_cached("SELECT id FROM countries WHERE code = ?1")?.query_row(params![country.code], |row| row.get(0))
+    }
+    
+    /// Returns the ID of the given season, creating it if needed
+    fn get_or_insert_season(&self, conn: &Connection, season: &Season) -> Result<i32> {
+        execute_cached(conn, 
+            "INSERT INTO seasons (name, start_year, end_year)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (name) DO NOTHING",
+            params![season.name, season.start_year, season.end_year]
+        )?;
+        
+        conn.prepare_cached("SELECT id FROM seasons WHERE name = ?1")?.query_row(params![season.name], |row| row.get(0))
+    }
+    
+    /// Inserts or updates a single match, identified by its league, date and teams, along with its goals.
+    /// Matches between teams that can't be found are skipped. Only fails if the database could not be read
+    /// or the batch the match is part of could not be committed.
+    #[allow(clippy::too_many_arguments)]
+    fn insert_match(&self, conn: &Connection, json_loader: &JsonLoader, report: &mut IngestReport, league: &LeagueInfo, stage: Option<&str>, round_name: &str, m: &OldLeagueMatch) -> Result<()> {
+        let match_date = m.get_date();
+        let league_id = league.id;
+        let source_file = Some(league.source_file.clone());
+        let description = format!("match {} {} vs {} ({})", match_date, m.team1, m.team2, round_name);
+        let team1_id = json_loader.get_team_id_from_name(&m.team1);
+        let team2_id = json_loader.get_team_id_from_name(&m.team2);
+        let team1_score = m.get_team_1_score();
+        let team2_score = m.get_team_2_score();
+        let score = m.get_score().cloned().unwrap_or_default();
+        
+        // The match is still loaded, without the phases that couldn't be read
+        for phase in &score.invalid_phases {
+            report.record(source_file.clone(), Some(description.clone()), IngestError::InvalidScore { message: phase.clone() });
+        }
+        
+        let (team1_id, team2_id) = match (team1_id, team2_id) {
+            (Some(t1), Some(t2)) => (t1, t2),
+            (None, _) => {
+                report.record(source_file, Some(description), IngestError::UnknownTeam { name: m.team1.clone() });
+                return Ok(());
+            },
+            (_, None) => {
+                report.record(source_file, Some(description), IngestError::UnknownTeam { name: m.team2.clone() });
+                return Ok(());
+            }
+        };
+        
+        let venue_id = match m.get_ground() {
+            Some(ground) => match self.get_or_insert_venue(conn, ground) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    report.record(source_file.clone(), Some(format!("venue {}", ground)), insert_failed("venues", e));
+                    None
+                }
+            },
+            None => None,
+        };
+        
+        let existing_id = find_id(conn, 
+            "SELECT id FROM matches WHERE league_id = ?1 AND date = ?2 AND team1_id = ?3 AND team2_id = ?4",
+            params![league_id, match_date, team1_id, team2_id]
+        )?;
+        
+        let upsert_res = execute_cached(conn, 
+            "INSERT INTO matches (
+                date, kickoff, kickoff_utc_offset, venue_id, league_id, team1_id, team2_id, stage, round, team1_score, team2_score,
+                team1_score_ht, team2_score_ht, team1_score_ft, team2_score_ft, team1_score_et, team2_score_et,
+                team1_score_p, team2_score_p, team1_score_agg, team2_score_agg
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)
+            ON CONFLICT (league_id, date, team1_id, team2_id) DO UPDATE SET
+                kickoff = excluded.kickoff, kickoff_utc_offset = excluded.kickoff_utc_offset, venue_id = excluded.venue_id,
+                stage = excluded.stage, round = excluded.round,
+                team1_score = excluded.team1_score, team2_score = excluded.team2_score,
+                team1_score_ht = excluded.team1_score_ht, team2_score_ht = excluded.team2_score_ht,
+                team1_score_ft = excluded.team1_score_ft, team2_score_ft = excluded.team2_score_ft,
+                team1_score_et = excluded.team1_score_et, team2_score_et = excluded.team2_score_et,
+                team1_score_p = excluded.team1_score_p, team2_score_p = excluded.team2_score_p,
+                team1_score_agg = excluded.team1_score_agg, team2_score_agg = excluded.team2_score_agg
+            WHERE (
+                kickoff, kickoff_utc_offset, venue_id, stage, round, team1_score, team2_score,
+                team1_score_ht, team2_score_ht, team1_score_ft, team2_score_ft, team1_score_et, team2_score_et,
+                team1_score_p, team2_score_p, team1_score_agg, team2_score_agg
+            ) IS NOT (
+                excluded.kickoff, excluded.kickoff_utc_offset, excluded.venue_id, excluded.stage, excluded.round,
+                excluded.team1_score, excluded.team2_score, excluded.team1_score_ht, excluded.team2_score_ht,
+                excluded.team1_score_ft, excluded.team2_score_ft, excluded.team1_score_et, excluded.team2_score_et,
+                excluded.team1_score_p, excluded.team2_score_p, excluded.team1_score_agg, excluded.team2_score_agg
+            )",
+            params![
+                match_date,
+                m.get_kickoff(),
+                m.get_utc_offset().map(|o| o.to_string()),
+                venue_id,
+                league_id,
+                team1_id,
+                team2_id,
+                stage,
+                round_name,
+                team1_score,
+                team2_score,
+                score.ht.map(|s| s.0),
+                score.ht.map(|s| s.1),
+                score.ft.map(|s| s.0),
+                score.ft.map(|s| s.1),
+                score.et.map(|s| s.0),
+                score.et.map(|s| s.1),
+                score.p.map(|s| s.0),
+                score.p.map(|s| s.1),
+                score.agg.map(|s| s.0),
+                score.agg.map(|s| s.1)
+            ]
+        );
+        
+        let changed = match upsert_res {
+            Ok(c) => c,
+            Err(e) => {
+                report.record(source_file, Some(description), insert_failed("matches", e));
+                return Ok(());
+            }
+        };
+        
+        let match_id = existing_id.unwrap_or_else(|| conn.last_insert_rowid());
+        let goals: Vec<(&Goal, i32, i32)> = m.goals1.iter().map(|g| (g, team1_id, team2_id))
+            .chain(m.goals2.iter().map(|g| (g, team2_id, team1_id)))
+            .collect();
+        
+        // Goals have no key of their own, so the goals of an existing match are replaced if any of them changed
+        let goals_changed = existing_id.is_none() || self.goals_differ(conn, match_id, &goals)?;
+        if goals_changed {
+            execute_cached(conn, "DELETE FROM goals WHERE match_id = ?1", params![match_id])?;
+            
+            for (goal, credited_team_id, opponent_id) in goals {
+                if let Err(e) = self.insert_goal(conn, match_id, goal, credited_team_id, opponent_id) {
+                    let item = format!("goal by {} ({}') in {}", goal.name, goal.minute, description);
+                    report.record(source_file.clone(), Some(item), insert_failed("goals", e));
+                }
+            }
+        }
+        
+        let outcome = match upsert_outcome(existing_id.is_some(), changed) {
+            UpsertOutcome::Unchanged if goals_changed => UpsertOutcome::Updated,
+            outcome => outcome,
+        };
+        report.matches.count(outcome);
+        
+        self.row_written(conn)
+    }
+    
+    /// Whether the goals stored for a match differ from the given ones, as (goal, credited team, opponent)
+    fn goals_differ(&self, conn: &Connection, match_id: i64, goals: &[(&Goal, i32, i32)]) -> Result<bool> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT goals.team_id, players.name, goals.minute, goals.minute_offset, goals.penalty, goals.owngoal
+            FROM goals
+            JOIN players ON players.id = goals.player_id
+            WHERE goals.match_id = ?1
+            ORDER BY goals.id"
+        )?;
+        let stored = stmt.query_map(params![match_id], |row| {
+            Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?, row.get::<_, i32>(2)?, row.get::<_, Option<i32>>(3)?, row.get::<_, bool>(4)?, row.get::<_, bool>(5)?))
+        })?.collect::<Result<Vec<_>>>()?;
+        
+        let expected: Vec<_> = goals.iter()
+            .map(|(g, credited_team_id, _)| (*credited_team_id, g.name.clone(), g.minute, g.offset, g.penalty, g.owngoal))
+            .collect();
+        
+        Ok(stored != expected)
+    }
+    
+    /// Returns the ID of the venue with the given name, creating it if needed
+    fn get_or_insert_venue(&self, conn: &Connection, name: &str) -> Result<i64> {
+        execute_cached(conn, 
+            "INSERT INTO venues (name)
+            VALUES (?1)
+            ON CONFLICT (name) DO NOTHING",
+            params![name]
+        )?;
+        
+        conn.prepare_cached("SELECT id FROM venues WHERE name = ?1")?.query_row(params![name], |row| row.get(0))
+    }
+    
+    /// Inserts a goal, creating its scorer in the players table if needed
+    fn insert_goal(&self, conn: &Connection, match_id: i64, goal: &Goal, credited_team_id: i32, opponent_id: i32) -> Result<()> {
+        // Own goals are scored by a player of the other team
+        let player_team_id = if goal.owngoal { opponent_id } else { credited_team_id };
+        
+        execute_cached(conn, 
+            "INSERT INTO players (name, team_id)
+            VALUES (?1, ?2)
+            ON CONFLICT (name, team_id) DO NOTHING",
+            params![goal.name, player_team_id]
+        )?;
+        
+        let player_id: i64 = conn.prepare_cached("SELECT id FROM players WHERE name = ?1 AND team_id = ?2")?
+            .query_row(params![goal.name, player_team_id], |row| row.get(0))?;
+        
+        execute_cached(conn, 
+            "INSERT INTO goals (match_id, team_id, player_id, minute, minute_offset, penalty, owngoal)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                match_id,
+                credited_team_id,
+                player_id,
+                goal.minute,
+                goal.offset,
+                goal.penalty,
+                goal.owngoal
+            ]
+        )?;
+        
         Ok(())
     }
     
-    /// Iterates through all matches and inserts them into the SQLite database.
-    fn insert_matches_from_loader(&self, json_loader: &JsonLoader) -> Result<()> {
-        info!("Inserting matches...");
-        let leagues = json_loader.get_leagues();
+    /// Iterates through all groups and inserts them, along with their teams, into the SQLite database.
+    fn insert_groups_from_loader(&self, json_loader: &JsonLoader, league_ids: &[Option<i32>], report: &mut IngestReport) -> Result<()> {
+        info!("Inserting groups...");
+        let group_lists = json_loader.get_groups();
         let conn = match &self.conn {
             Some(c) => c,
             None => {
@@ -164,107 +1119,413 @@ impl DatabaseProcessor {
                 return Err(Error::InvalidQuery);
             }
         };
-
-        for league in leagues.iter() {
-            match league {
-                LeagueJsonFormat::OldFormat(old_league) => {
-                    let league_id = old_league.id;
-                    let stage: Option<String> = None;
-                    
-                    for round in old_league.rounds.iter() {
-                        let round_name = &round.name;
-                        
-                        for m in round.matches.iter() {
-                            let match_date = m.get_date();
-                            let team1_id = json_loader.get_team_id_from_name(&m.team1);
-                            let team2_id = json_loader.get_team_id_from_name(&m.team2);
-                            let team1_score = m.get_team_1_score();
-                            let team2_score = m.get_team_2_score();
-                            
-                            if let None = team1_id {
-                                warn!("Skipping match data...");
-                                warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
-                                continue;
-                            }
-                            
-                            if let None = team2_id {
-                                warn!("Skipping match data...");
-                                warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
-                                continue;
-                            }
-                            
-                            let insert_res = conn.execute(
-                                "INSERT INTO matches (date, league_id, team1_id, team2_id, stage, round, team1_score, team2_score)
-                                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                                params![
-                                    match_date,
-                                    league_id,
-                                    team1_id.unwrap_or(-1),
-                                    team2_id.unwrap_or(-1),
-                                    stage.clone(),
-                                    round_name,
-                                    team1_score,
-                                    team2_score
-                                ]
-                            );
-                            
-                            if let Err(e) = insert_res {
-                                warn!("Failed to insert match: {}", e.to_string());
-                                warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
-                                continue;
-                            }
-                        }
+        
+        for (group_list, league_id) in group_lists.iter().zip(league_ids) {
+            let league_id = match league_id {
+                Some(id) => *id,
+                None => {
+                    report.record(Some(group_list.source_file.clone()), None, IngestError::MissingLeague);
+                    continue;
+                }
+            };
+            
+            for group in group_list.groups.iter() {
+                let existing_id = find_id(conn, "SELECT id FROM groups WHERE id = ?1", params![group.id])?;
+                
+                let upsert_res = execute_cached(conn, 
+                    "INSERT INTO groups (id, league_id, name, source_file)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT (id) DO UPDATE SET league_id = excluded.league_id, name = excluded.name,
+                        source_file = excluded.source_file
+                    WHERE (league_id, name, source_file) IS NOT (excluded.league_id, excluded.name, excluded.source_file)",
+                    params![
+                        group.id,
+                        league_id,
+                        group.name,
+                        group_list.source_file
+                    ]
+                );
+                
+                let changed = match upsert_res {
+                    Ok(c) => c,
+                    Err(e) => {
+                        report.record(Some(group_list.source_file.clone()), Some(group.name.clone()), insert_failed("groups", e));
+                        continue;
                     }
-                },
-                LeagueJsonFormat::NewFormat(new_league) => {
-                    let league_id = new_league.id;
-                    
-                    for m in new_league.matches.iter() {
-                        let stage = m.stage.clone();
-                        let round_name = m.round.clone();
-                        let match_date = m.get_date();
-                        let team1_id = json_loader.get_team_id_from_name(&m.get_team_1_name().to_string());
-                        let team2_id = json_loader.get_team_id_from_name(&m.get_team_2_name().to_string());
-                        let team1_score = m.get_team_1_score();
-                        let team2_score = m.get_team_2_score();
-                        
-                        if let None = team1_id {
-                            warn!("Skipping match data...");
-                            warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
-                            continue;
-                        }
-                        
-                        if let None = team2_id {
-                            warn!("Skipping match data...");
-                            warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
-                            continue;
-                        }
-                        
-                        let insert_res = conn.execute(
-                            "INSERT INTO matches (date, league_id, team1_id, team2_id, stage, round, team1_score, team2_score)
-                            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                            params![
-                                match_date,
-                                league_id,
-                                team1_id.unwrap_or(-1),
-                                team2_id.unwrap_or(-1),
-                                stage,
-                                round_name,
-                                team1_score,
-                                team2_score
-                            ]
-                        );
-                        
-                        if let Err(e) = insert_res {
-                            warn!("Failed to insert match: {}", e.to_string());
-                            warn!("Data: {} {} {} {} {} {} {} {}", match_date, league_id, team1_id.unwrap_or(-1), team2_id.unwrap_or(-1), stage.clone().unwrap_or("".to_string()), round_name, team1_score.unwrap_or(-1), team2_score.unwrap_or(-1));
+                };
+                self.row_written(conn)?;
+                
+                // Members are compared as a whole, so teams that left the group are removed as well
+                let stored_team_ids = conn.prepare_cached("SELECT team_id FROM group_teams WHERE group_id = ?1 ORDER BY team_id")?
+                    .query_map(params![group.id], |row| row.get::<_, i32>(0))?
+                    .collect::<Result<Vec<i32>>>()?;
+                execute_cached(conn, "DELETE FROM group_teams WHERE group_id = ?1", params![group.id])?;
+                
+                for team in group.teams.iter() {
+                    let team_id = match json_loader.get_team_id_from_name(&team.get_name().to_string()) {
+                        Some(id) => id,
+                        None => {
+                            let name = team.get_name().to_string();
+                            report.record(Some(group_list.source_file.clone()), Some(group.name.clone()), IngestError::UnknownTeam { name });
                             continue;
                         }
+                    };
+                    
+                    let insert_res = execute_cached(conn, 
+                        "INSERT INTO group_teams (group_id, team_id)
+                        VALUES (?1, ?2)
+                        ON CONFLICT (group_id, team_id) DO NOTHING",
+                        params![group.id, team_id]
+                    );
+                    
+                    if let Err(e) = insert_res {
+                        let item = format!("{} in {}", team.get_name(), group.name);
+                        report.record(Some(group_list.source_file.clone()), Some(item), insert_failed("group_teams", e));
+                        continue;
                     }
                 }
-            };
+                
+                let team_ids = conn.prepare_cached("SELECT team_id FROM group_teams WHERE group_id = ?1 ORDER BY team_id")?
+                    .query_map(params![group.id], |row| row.get::<_, i32>(0))?
+                    .collect::<Result<Vec<i32>>>()?;
+                
+                let outcome = match upsert_outcome(existing_id.is_some(), changed) {
+                    UpsertOutcome::Unchanged if team_ids != stored_team_ids => UpsertOutcome::Updated,
+                    outcome => outcome,
+                };
+                report.groups.count(outcome);
+            }
         }
-
+        
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Works out what an upsert did from whether the row existed before and the number of rows the statement changed
+fn upsert_outcome(existed: bool, changed: usize) -> UpsertOutcome {
+    match (existed, changed) {
+        (false, _) => UpsertOutcome::Inserted,
+        (true, 0) => UpsertOutcome::Unchanged,
+        (true, _) => UpsertOutcome::Updated,
+    }
+}
+
+/// Whether a match of the given stage belongs to the group stage of a group. Matches without a stage
+/// are counted, as older files only give the stage of knockout matches.
+fn is_group_stage(stage: Option<&str>, group_name: &str) -> bool {
+    match stage {
+        None => true,
+        Some(s) => s.eq_ignore_ascii_case(group_name) || s.to_lowercase().contains("group"),
+    }
+}
+
+/// Returns the ID found by a query, if it found a row
+fn find_id<P: Params>(conn: &Connection, sql: &str, params: P) -> Result<Option<i64>> {
+    conn.prepare_cached(sql)?.query_row(params, |row| row.get(0)).optional()
+}
+
+fn insert_failed(table: &str, e: Error) -> IngestError {
+    IngestError::InsertFailed { table: table.to_string(), message: e.to_string() }
+}
+
+/// Runs a statement through the statement cache of the connection, so it is only prepared once per load
+fn execute_cached<P: Params>(conn: &Connection, sql: &str, params: P) -> Result<usize> {
+    conn.prepare_cached(sql)?.execute(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::report::IngestEntry;
+
+    /// A football.json checkout with one league in a temporary directory, removed when dropped
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("football_json_loader_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let fixture = Fixture { dir };
+
+            fixture.write("2020-21/en.1.clubs.json", r#"{
+                "name": "English Premier League 2020/21",
+                "clubs": [
+                    {"name": "Arsenal FC", "code": "ARS", "country": "England"},
+                    {"name": "Fulham FC", "code": "FUL", "country": "England"}
+                ]
+            }"#);
+            fixture.write("2020-21/en.1.json", r#"{
+                "name": "English Premier League 2020/21",
+                "matches": [
+                    {"round": "Matchday 1", "date": "2020-09-12", "team1": "Fulham FC", "team2": "Arsenal FC", "score": {"ft": [0, 3]}},
+                    {"round": "Matchday 2", "date": "2020-09-19", "team1": "Arsenal FC", "team2": "Fulham FC", "score": {"ft": [1, 1]}}
+                ]
+            }"#);
+
+            fixture
+        }
+
+        /// Writes a file into the checkout, given its path relative to the football.json root
+        fn write(&self, file: &str, content: &str) {
+            let path = self.dir.join("json").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        fn database_path(&self) -> String {
+            self.dir.join("football_json.sqlite").to_string_lossy().to_string()
+        }
+
+        /// Removes a file from the checkout
+        fn remove(&self, file: &str) {
+            fs::remove_file(self.dir.join("json").join(file)).unwrap();
+        }
+
+        /// Loads the checkout into the database the way a full load does
+        fn load(&self) -> IngestReport {
+            let mut db_processor = DatabaseProcessor::new();
+            db_processor.init(&self.database_path()).unwrap();
+            db_processor.begin_transaction().unwrap();
+
+            let mut json_loader = JsonLoader::with_root_dir(self.dir.join("json"));
+            db_processor.prepare_incremental_load(&mut json_loader).unwrap();
+            json_loader.init().unwrap();
+            self.insert(&mut db_processor, &mut json_loader, &[])
+        }
+
+        /// Loads the given files into the database the way an incremental load does
+        fn load_changes(&self, changed: &[&str], deleted: &[&str]) -> IngestReport {
+            let mut db_processor = DatabaseProcessor::new();
+            db_processor.init(&self.database_path()).unwrap();
+            db_processor.begin_transaction().unwrap();
+
+            let mut stale_files: Vec<String> = changed.iter().chain(deleted).map(|f| f.to_string()).collect();
+            let mut changed: Vec<PathBuf> = changed.iter().map(PathBuf::from).collect();
+            for linked_file in db_processor.get_linked_groups_files(&stale_files).unwrap() {
+                changed.push(PathBuf::from(&linked_file));
+                stale_files.push(linked_file);
+            }
+            db_processor.remove_leagues_from_files(&stale_files).unwrap();
+
+            let mut json_loader = JsonLoader::with_root_dir(self.dir.join("json"));
+            db_processor.prepare_incremental_load(&mut json_loader).unwrap();
+            json_loader.init_with_files(&changed);
+
+            let deleted: Vec<String> = deleted.iter().map(|f| f.to_string()).collect();
+            self.insert(&mut db_processor, &mut json_loader, &deleted)
+        }
+
+        fn insert(&self, db_processor: &mut DatabaseProcessor, json_loader: &mut JsonLoader, deleted: &[String]) -> IngestReport {
+            json_loader.load_teams().unwrap();
+            json_loader.load_groups().unwrap();
+
+            let mut report = json_loader.get_report().clone();
+            report.merge(db_processor.insert_data_streaming(json_loader).unwrap());
+            db_processor.remove_teams_from_files(deleted).unwrap();
+            db_processor.commit_transaction().unwrap();
+            report
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn full_load_into_version_0_database_keeps_team_ids() {
+        let fixture = Fixture::new("version_0");
+
+        // Teams of a version 0 database were numbered in load order, not by the hash of their name
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        conn.execute_batch("
+            CREATE TABLE teams (id INTEGER PRIMARY KEY, name TEXT NOT NULL, code TEXT);
+            CREATE TABLE leagues (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE TABLE matches (
+                id INTEGER PRIMARY KEY, date TEXT NOT NULL,
+                league_id INTEGER NOT NULL REFERENCES leagues(id),
+                team1_id INTEGER NOT NULL REFERENCES teams(id),
+                team2_id INTEGER NOT NULL REFERENCES teams(id),
+                stage TEXT, round TEXT, team1_score INTEGER, team2_score INTEGER
+            );
+            INSERT INTO teams (id, name, code) VALUES (56, 'Arsenal FC', 'ARS');
+        ").unwrap();
+        drop(conn);
+
+        let report = fixture.load();
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.teams.inserted, 1);
+        assert_eq!(report.teams.updated, 1);
+        assert_eq!(report.matches.inserted, 2);
+
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let arsenal_id: i32 = conn.query_row("SELECT id FROM teams WHERE name = 'Arsenal FC'", (), |row| row.get(0)).unwrap();
+        assert_eq!(arsenal_id, 56);
+
+        let home_matches: i32 = conn.query_row("SELECT COUNT(*) FROM matches WHERE team1_id = 56", (), |row| row.get(0)).unwrap();
+        assert_eq!(home_matches, 1);
+    }
+
+    #[test]
+    fn groups_without_league_file_are_linked_by_name() {
+        let fixture = Fixture::new("groups");
+        fixture.write("2020-21/groups.groups.json", r#"{
+            "name": "English Premier League 2020/21",
+            "groups": [{"name": "Group A", "teams": ["Arsenal FC", "Fulham FC"]}]
+        }"#);
+
+        let report = fixture.load();
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.groups.inserted, 1);
+
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let league_name: String = conn.query_row(
+            "SELECT leagues.name FROM groups JOIN leagues ON leagues.id = groups.league_id", (), |row| row.get(0)
+        ).unwrap();
+        assert_eq!(league_name, "English Premier League 2020/21");
+    }
+
+    #[test]
+    fn group_tables_leave_out_knockout_matches() {
+        assert!(is_group_stage(None, "Group A"));
+        assert!(is_group_stage(Some("Group stage"), "Group A"));
+        assert!(is_group_stage(Some("group a"), "Group A"));
+        assert!(!is_group_stage(Some("Knockout"), "Group A"));
+        assert!(!is_group_stage(Some("Final"), "Group A"));
+    }
+
+    #[test]
+    fn loading_twice_changes_nothing() {
+        let fixture = Fixture::new("twice");
+        fixture.load();
+        let report = fixture.load();
+
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.teams.unchanged, 2);
+        assert_eq!(report.leagues.unchanged, 1);
+        assert_eq!(report.matches.unchanged, 2);
+        assert_eq!(report.teams.inserted + report.teams.updated + report.matches.inserted + report.matches.updated, 0);
+    }
+
+    #[test]
+    fn declared_teams_with_the_same_normalized_name_stay_apart() {
+        let fixture = Fixture::new("collision");
+        fixture.write("2020-21/en.1.clubs.json", r#"{
+            "name": "English Premier League 2020/21",
+            "clubs": [
+                {"name": "Arsenal FC", "code": "ARS", "country": "England"},
+                {"name": "Fulham FC", "code": "FUL", "country": "England"},
+                {"name": "Liverpool FC", "code": "LIV", "country": "England"}
+            ]
+        }"#);
+        fixture.write("2020/uy.1.clubs.json", r#"{
+            "name": "Uruguay Primera División 2020",
+            "clubs": [{"name": "Liverpool", "country": "Uruguay"}]
+        }"#);
+        fixture.write("2020-21/en.1.json", r#"{
+            "name": "English Premier League 2020/21",
+            "matches": [
+                {"round": "Matchday 1", "date": "2020-09-12", "team1": "Fulham FC", "team2": "Arsenal FC", "score": {"ft": [0, 3]}},
+                {"round": "Matchday 2", "date": "2020-09-19", "team1": "Liverpool F.C.", "team2": "Arsenal", "score": {"ft": [3, 1]}}
+            ]
+        }"#);
+
+        let report = fixture.load();
+        assert_eq!(report.teams.inserted, 4);
+        assert_eq!(report.matches.inserted, 1);
+        assert!(matches!(&report.entries[..], [IngestEntry { error: IngestError::UnknownTeam { name }, .. }] if name == "Liverpool F.C."),
+            "{:?}", report.entries);
+
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let aliases: Vec<String> = conn.prepare("SELECT alias FROM team_aliases ORDER BY alias").unwrap()
+            .query_map((), |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(aliases, vec!["Arsenal"]);
+    }
+
+    #[test]
+    fn teams_declared_in_several_files_survive_deleting_one() {
+        let fixture = Fixture::new("team_sources");
+        fixture.write("2021-22/en.1.clubs.json", r#"{
+            "name": "English Premier League 2021/22",
+            "clubs": [
+                {"name": "Arsenal FC", "code": "ARS", "country": "England"},
+                {"name": "Brentford FC", "code": "BRE", "country": "England"}
+            ]
+        }"#);
+        fixture.load();
+
+        fixture.write("2021-22/en.1.clubs.json", r#"{
+            "name": "English Premier League 2021/22",
+            "clubs": [
+                {"name": "Arsenal FC", "code": "ARS", "country": "England"},
+                {"name": "Brentford FC", "code": "BRE", "country": "England"},
+                {"name": "Watford FC", "code": "WAT", "country": "England"}
+            ]
+        }"#);
+        let report = fixture.load_changes(&["2021-22/en.1.clubs.json"], &[]);
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.teams.inserted, 1);
+
+        // The source file is the first file declaring the team, as after a full load
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let source_file: String = conn.query_row("SELECT source_file FROM teams WHERE name = 'Arsenal FC'", (), |row| row.get(0)).unwrap();
+        assert_eq!(source_file, "2020-21/en.1.clubs.json");
+
+        fixture.remove("2021-22/en.1.clubs.json");
+        fixture.load_changes(&[], &["2021-22/en.1.clubs.json"]);
+
+        let teams: Vec<String> = conn.prepare("SELECT name FROM teams ORDER BY name").unwrap()
+            .query_map((), |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(teams, vec!["Arsenal FC", "Fulham FC"]);
+    }
+
+    #[test]
+    fn groups_linked_by_name_are_reloaded_with_their_league() {
+        let fixture = Fixture::new("linked_groups");
+        fixture.write("2020-21/groups.groups.json", r#"{
+            "name": "English Premier League 2020/21",
+            "groups": [{"name": "Group A", "teams": ["Arsenal FC", "Fulham FC"]}]
+        }"#);
+        fixture.load();
+
+        let report = fixture.load_changes(&["2020-21/en.1.json"], &[]);
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.groups.inserted, 1);
+
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let league_name: String = conn.query_row(
+            "SELECT leagues.name FROM groups JOIN leagues ON leagues.id = groups.league_id WHERE groups.name = 'Group A'",
+            (), |row| row.get(0)
+        ).unwrap();
+        assert_eq!(league_name, "English Premier League 2020/21");
+    }
+
+    #[test]
+    fn insert_without_a_transaction_commits_on_its_own() {
+        let fixture = Fixture::new("own_transaction");
+        let mut db_processor = DatabaseProcessor::new();
+        db_processor.init(&fixture.database_path()).unwrap();
+        db_processor.set_batch_size(1);
+
+        let mut json_loader = JsonLoader::with_root_dir(fixture.dir.join("json"));
+        json_loader.init().unwrap();
+        json_loader.load_teams().unwrap();
+        json_loader.load_groups().unwrap();
+
+        let report = db_processor.insert_data_streaming(&mut json_loader).unwrap();
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.matches.inserted, 2);
+        drop(db_processor);
+
+        let conn = Connection::open(fixture.database_path()).unwrap();
+        let matches: i32 = conn.query_row("SELECT COUNT(*) FROM matches", (), |row| row.get(0)).unwrap();
+        assert_eq!(matches, 2);
+    }
+}
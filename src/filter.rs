@@ -0,0 +1,171 @@
+use std::{fmt, path::{Path, PathBuf}};
+
+use glob::{MatchOptions, Pattern, PatternError};
+
+/// `*` does not cross directory boundaries, `**` does
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Decides which files of the football.json tree are loaded, based on glob patterns
+/// matched against the path relative to the root directory.
+#[derive(Default)]
+pub struct FileFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+/// Why a file was or was not selected
+#[derive(Clone, PartialEq, Debug)]
+pub enum SelectionReason {
+    /// Not a .json file
+    NotJson,
+    /// No include patterns were given, so every JSON file is included
+    IncludedByDefault,
+    Included(String),
+    Excluded(String),
+    NotIncluded,
+}
+
+impl fmt::Display for SelectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionReason::NotJson => write!(f, "not a JSON file"),
+            SelectionReason::IncludedByDefault => write!(f, "included by default"),
+            SelectionReason::Included(p) => write!(f, "matched include pattern {}", p),
+            SelectionReason::Excluded(p) => write!(f, "matched exclude pattern !{}", p),
+            SelectionReason::NotIncluded => write!(f, "did not match any include pattern"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileSelection {
+    /// Path relative to the root directory
+    pub path: PathBuf,
+    pub selected: bool,
+    pub reason: SelectionReason,
+}
+
+impl FileFilter {
+    /// Creates a filter from lists of include and exclude patterns. Include patterns
+    /// starting with `!` are treated as exclude patterns.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, PatternError> {
+        let mut filter = FileFilter::default();
+
+        for pattern in include {
+            match pattern.strip_prefix('!') {
+                Some(p) => filter.exclude.push(Pattern::new(p)?),
+                None => filter.include.push(Pattern::new(pattern)?),
+            };
+        }
+
+        for pattern in exclude {
+            let p = pattern.strip_prefix('!').unwrap_or(pattern);
+            filter.exclude.push(Pattern::new(p)?);
+        }
+
+        Ok(filter)
+    }
+
+    /// Decides whether a file is loaded. Exclude patterns take precedence over include patterns.
+    pub fn select(&self, relative_path: &Path) -> FileSelection {
+        let reason = self.reason(relative_path);
+        let selected = matches!(reason, SelectionReason::IncludedByDefault | SelectionReason::Included(_));
+
+        FileSelection { path: relative_path.to_path_buf(), selected, reason }
+    }
+
+    fn reason(&self, relative_path: &Path) -> SelectionReason {
+        if relative_path.extension().is_none_or(|ext| ext != "json") {
+            return SelectionReason::NotJson;
+        }
+
+        if let Some(p) = self.exclude.iter().find(|p| p.matches_path_with(relative_path, MATCH_OPTIONS)) {
+            return SelectionReason::Excluded(p.as_str().to_string());
+        }
+
+        if self.include.is_empty() {
+            return SelectionReason::IncludedByDefault;
+        }
+
+        match self.include.iter().find(|p| p.matches_path_with(relative_path, MATCH_OPTIONS)) {
+            Some(p) => SelectionReason::Included(p.as_str().to_string()),
+            None => SelectionReason::NotIncluded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> FileFilter {
+        let include: Vec<String> = include.iter().map(|p| p.to_string()).collect();
+        let exclude: Vec<String> = exclude.iter().map(|p| p.to_string()).collect();
+        FileFilter::new(&include, &exclude).unwrap()
+    }
+
+    fn reason(filter: &FileFilter, path: &str) -> SelectionReason {
+        filter.select(Path::new(path)).reason
+    }
+
+    #[test]
+    fn every_json_file_is_included_without_patterns() {
+        let filter = filter(&[], &[]);
+
+        assert!(filter.select(Path::new("2020-21/en.1.json")).selected);
+        assert_eq!(reason(&filter, "2020-21/en.1.json"), SelectionReason::IncludedByDefault);
+        assert!(!filter.select(Path::new("README.md")).selected);
+        assert_eq!(reason(&filter, "README.md"), SelectionReason::NotJson);
+        assert_eq!(reason(&filter, "2020-21/en.1.json.bak"), SelectionReason::NotJson);
+        assert_eq!(reason(&filter, "2020-21/en"), SelectionReason::NotJson);
+    }
+
+    #[test]
+    fn single_star_stays_within_a_directory() {
+        let filter = filter(&["2020-21/*.json"], &[]);
+
+        assert_eq!(reason(&filter, "2020-21/en.1.json"), SelectionReason::Included("2020-21/*.json".to_string()));
+        assert_eq!(reason(&filter, "2020-21/europe/cl.json"), SelectionReason::NotIncluded);
+        assert_eq!(reason(&filter, "2021-22/en.1.json"), SelectionReason::NotIncluded);
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        let filter = filter(&["**/en.1.json", "europe/**/cl.clubs.json"], &[]);
+
+        assert!(filter.select(Path::new("en.1.json")).selected);
+        assert!(filter.select(Path::new("2020-21/en.1.json")).selected);
+        assert!(filter.select(Path::new("archive/2020-21/en.1.json")).selected);
+        assert!(filter.select(Path::new("europe/cl.clubs.json")).selected);
+        assert!(filter.select(Path::new("europe/cl/2020-21/cl.clubs.json")).selected);
+        assert!(!filter.select(Path::new("2020-21/en.2.json")).selected);
+    }
+
+    #[test]
+    fn exclude_patterns_win_over_include_patterns() {
+        let filter = filter(&["2020-21/*.json", "!**/*.groups.json"], &["2020-21/en.2.json"]);
+
+        assert!(filter.select(Path::new("2020-21/en.1.json")).selected);
+        assert_eq!(reason(&filter, "2020-21/worldcup.groups.json"), SelectionReason::Excluded("**/*.groups.json".to_string()));
+        assert_eq!(reason(&filter, "worldcup.groups.json"), SelectionReason::Excluded("**/*.groups.json".to_string()));
+        assert_eq!(reason(&filter, "2020-21/en.2.json"), SelectionReason::Excluded("2020-21/en.2.json".to_string()));
+    }
+
+    #[test]
+    fn exclude_patterns_alone_keep_every_other_json_file() {
+        let filter = filter(&[], &["!2020-21/**"]);
+
+        assert_eq!(reason(&filter, "2020-21/en.1.json"), SelectionReason::Excluded("2020-21/**".to_string()));
+        assert_eq!(reason(&filter, "2021-22/en.1.json"), SelectionReason::IncludedByDefault);
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(FileFilter::new(&["2020-21/[.json".to_string()], &[]).is_err());
+        assert!(FileFilter::new(&[], &["***".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+/// Hands out IDs derived from natural keys (e.g. a team name), so the same key gets
+/// the same ID every time the database is generated, regardless of load order.
+#[derive(Default)]
+pub struct IdAllocator {
+    /// ID -> natural key it was given to
+    used: HashMap<i32, String>,
+}
+
+impl IdAllocator {
+    pub fn new() -> Self {
+        IdAllocator { used: HashMap::new() }
+    }
+
+    /// Returns the ID for a key. If the hash of the key collides with the ID of another key,
+    /// the next free ID is used instead.
+    pub fn allocate(&mut self, key: &str) -> i32 {
+        let mut id = stable_id(key);
+
+        loop {
+            match self.used.get(&id) {
+                None => {
+                    self.used.insert(id, key.to_string());
+                    return id;
+                },
+                Some(k) if k == key => return id,
+                Some(_) => id = id % i32::MAX + 1,
+            };
+        }
+    }
+
+    /// Returns the ID already allocated or reserved for a key
+    pub fn get(&self, key: &str) -> Option<i32> {
+        let mut id = stable_id(key);
+
+        loop {
+            match self.used.get(&id) {
+                None => return None,
+                Some(k) if k == key => return Some(id),
+                Some(_) => id = id % i32::MAX + 1,
+            };
+        }
+    }
+
+    /// Marks an ID as taken by a key, e.g. for IDs already stored in the database
+    pub fn reserve(&mut self, id: i32, key: &str) {
+        self.used.insert(id, key.to_string());
+    }
+}
+
+/// Hashes a key into a positive ID. Uses FNV-1a, since the hashers of the standard
+/// library are not guaranteed to be stable across Rust releases.
+pub fn stable_id(key: &str) -> i32 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    (hash % i32::MAX as u64) as i32 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_id_does_not_change() {
+        // IDs end up in databases, so the hash must never change
+        assert_eq!(stable_id("Arsenal FC"), 231658818);
+        assert_eq!(stable_id("2020-21/en.1.json"), 2043478830);
+        assert!(stable_id("") > 0);
+    }
+
+    #[test]
+    fn same_key_gets_same_id() {
+        let mut ids = IdAllocator::new();
+        let id = ids.allocate("Arsenal FC");
+
+        assert_eq!(id, stable_id("Arsenal FC"));
+        assert_eq!(ids.allocate("Arsenal FC"), id);
+        assert_ne!(ids.allocate("Fulham FC"), id);
+    }
+
+    #[test]
+    fn colliding_keys_get_the_next_free_id() {
+        let mut ids = IdAllocator::new();
+        let hash = stable_id("Arsenal FC");
+        ids.reserve(hash, "Stored team");
+        ids.reserve(hash + 1, "Another stored team");
+
+        assert_eq!(ids.allocate("Arsenal FC"), hash + 2);
+        assert_eq!(ids.allocate("Arsenal FC"), hash + 2);
+    }
+
+    #[test]
+    fn reserved_ids_are_kept_for_their_key() {
+        let mut ids = IdAllocator::new();
+        let hash = stable_id("2020-21/en.1.json");
+        ids.reserve(hash, "2020-21/en.1.json");
+
+        assert_eq!(ids.allocate("2020-21/en.1.json"), hash);
+    }
+
+    #[test]
+    fn get_finds_allocated_and_reserved_ids() {
+        let mut ids = IdAllocator::new();
+        let hash = stable_id("Arsenal FC");
+        ids.reserve(hash, "Stored team");
+        let id = ids.allocate("Arsenal FC");
+        ids.reserve(stable_id("2020-21/en.1.json"), "2020-21/en.1.json");
+
+        assert_eq!(ids.get("Arsenal FC"), Some(id));
+        assert_eq!(ids.get("2020-21/en.1.json"), Some(stable_id("2020-21/en.1.json")));
+        assert_eq!(ids.get("Fulham FC"), None);
+    }
+}
@@ -1,92 +1,428 @@
-use std::{path::{PathBuf, Path}, fs, io, collections::HashMap, rc::Rc};
+use std::{path::{PathBuf, Path}, fs, io, fmt, str::FromStr, collections::HashMap, sync::{Arc, atomic::{AtomicUsize, Ordering}}, thread};
 
 use log::{warn, info};
+use serde::de::DeserializeOwned;
 
-use crate::model::{Team, MatchTeamList, LeagueJsonFormat};
+use crate::{model::{Team, TeamOrigin, MatchTeamList, LeagueJsonFormat, GroupList}, filter::{FileFilter, FileSelection}, classify::{classify_file, companion_file, FileKind}, competition::{competition_code, Season}, alias::{AliasMap, AliasSource, normalize_team_name, name_similarity}, id::IdAllocator, report::{IngestError, IngestReport}, stream::{LeagueStream, PendingLeagueFile}};
+
+pub const DEFAULT_ROOT_DIR: &str = "/tmp/football.json";
+
+/// What to do with teams that take part in matches or groups but are missing from the clubs files
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MissingTeamPolicy {
+    /// Leave the teams out. Their matches are skipped.
+    #[default]
+    Skip,
+    /// Create the teams, marked as inferred
+    AutoCreate,
+    /// Treat missing teams as an error
+    Fail,
+}
+
+impl FromStr for MissingTeamPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MissingTeamPolicy::Skip),
+            "create" => Ok(MissingTeamPolicy::AutoCreate),
+            "fail" => Ok(MissingTeamPolicy::Fail),
+            _ => Err(format!("Unknown missing team policy: {} (expected skip, create or fail)", s)),
+        }
+    }
+}
+
+/// Natural key of a group, used to derive its ID
+fn group_key(source_file: &str, name: &str) -> String {
+    format!("{}#{}", source_file, name)
+}
+
+/// Reads and deserializes files using up to `jobs` threads. The results are returned in the order of `paths`,
+/// however the work was spread across the threads.
+pub(crate) fn parse_files<T: DeserializeOwned + Send>(paths: &[PathBuf], jobs: usize) -> Vec<(PathBuf, Result<T, IngestError>)> {
+    let parse = |path: &Path| -> Result<T, IngestError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| IngestError::UnreadableFile { message: e.to_string() })?;
+        serde_json::from_str(&content)
+            .map_err(|e| IngestError::InvalidJson { message: e.to_string() })
+    };
+    
+    let jobs = jobs.clamp(1, paths.len().max(1));
+    if jobs == 1 {
+        return paths.iter().map(|p| (p.clone(), parse(p))).collect();
+    }
+    
+    // Each thread takes the next file that hasn't been taken yet, so slow files don't hold up a whole thread's share
+    let next_index = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<T, IngestError>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs).map(|_| scope.spawn(|| {
+            let mut parsed = Vec::new();
+            loop {
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                match paths.get(index) {
+                    Some(path) => parsed.push((index, parse(path))),
+                    None => return parsed,
+                };
+            }
+        })).collect();
+        
+        handles.into_iter()
+            .flat_map(|h| h.join().expect("Parser thread panicked"))
+            .collect()
+    });
+    
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter()
+        .map(|(index, result)| (paths[index].clone(), result))
+        .collect()
+}
+
+/// Sets the metadata of a league that is derived from the file it was loaded from
+pub(crate) fn prepare_league(league: &mut LeagueJsonFormat, file_path: &Path, source_file: String, league_id: i32) {
+    league.set_league_id(league_id);
+    league.set_competition_code(competition_code(file_path));
+    league.set_season(Season::from_path(Path::new(&source_file)));
+    league.set_source_file(source_file);
+}
+
+/// How resolve_team_name found a team
+pub enum TeamResolution {
+    /// The name or one of its spellings belongs to a known team
+    Known(i32),
+    /// The team was created according to MissingTeamPolicy::AutoCreate
+    Created(Arc<Team>),
+    /// The team is missing and was not created
+    Unknown,
+}
+
+/// The teams that are missing when the policy is MissingTeamPolicy::Fail
+#[derive(Debug)]
+pub struct MissingTeamsError {
+    pub names: Vec<String>,
+}
+
+impl fmt::Display for MissingTeamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} teams are missing from the clubs files: {}", self.names.len(), self.names.join(", "))
+    }
+}
 
 pub struct JsonLoader {
-    files: Vec<PathBuf>,
-    pub teams: Vec<Rc<Team>>,
-    team_name_map: HashMap<String, Rc<Team>>,
-    pub leagues: Vec<LeagueJsonFormat>,
+    root_dir: PathBuf,
+    file_filter: FileFilter,
+    file_selections: Vec<FileSelection>,
+    files: Vec<(PathBuf, FileKind)>,
+    pub teams: Vec<Arc<Team>>,
+    team_name_map: HashMap<String, Arc<Team>>,
+    /// Normalized name -> every team with that normalized name
+    normalized_team_map: HashMap<String, Vec<Arc<Team>>>,
+    alias_map: AliasMap,
+    /// Alternative spellings of team names that were resolved to a team
+    pub aliases: Vec<(String, Arc<Team>, AliasSource)>,
+    pub groups: Vec<GroupList>,
+    team_ids: IdAllocator,
+    league_ids: IdAllocator,
+    group_ids: IdAllocator,
+    missing_team_policy: MissingTeamPolicy,
+    /// Names resolve_team_name could not find a team for
+    unknown_team_names: Vec<String>,
+    /// Number of threads used to read and deserialize files
+    jobs: usize,
+    /// Files and records skipped while loading
+    report: IngestReport,
     is_initialized: bool
 }
 
+// The loader is meant to be usable from multithreaded services
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<JsonLoader>;
+};
+
+impl Default for JsonLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl JsonLoader {
+    /// Creates a loader reading from the default football.json location
     pub fn new() -> Self {
-        return JsonLoader {
+        Self::with_root_dir(DEFAULT_ROOT_DIR)
+    }
+
+    /// Creates a loader reading from a football.json checkout located at `root_dir`
+    pub fn with_root_dir<P: AsRef<Path>>(root_dir: P) -> Self {
+        JsonLoader {
+            root_dir: root_dir.as_ref().to_path_buf(),
+            file_filter: FileFilter::default(),
+            file_selections: Vec::new(),
             files: Vec::new(),
             teams: Vec::new(),
             team_name_map: HashMap::new(),
-            leagues: Vec::new(),
+            normalized_team_map: HashMap::new(),
+            alias_map: AliasMap::default(),
+            aliases: Vec::new(),
+            groups: Vec::new(),
+            team_ids: IdAllocator::new(),
+            league_ids: IdAllocator::new(),
+            group_ids: IdAllocator::new(),
+            missing_team_policy: MissingTeamPolicy::default(),
+            unknown_team_names: Vec::new(),
+            jobs: 1,
+            report: IngestReport::new(),
             is_initialized: false,
+        }
+    }
+    
+    pub fn get_root_dir(&self) -> &Path {
+        &self.root_dir
+    }
+    
+    /// Only files selected by this filter are loaded
+    pub fn set_file_filter(&mut self, filter: FileFilter) {
+        self.file_filter = filter;
+    }
+    
+    /// Decides what resolve_team_name does with teams missing from the clubs files
+    pub fn set_missing_team_policy(&mut self, policy: MissingTeamPolicy) {
+        self.missing_team_policy = policy;
+    }
+    
+    /// Sets how many threads read and deserialize files. 0 uses one thread per CPU.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = match jobs {
+            0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n,
         };
     }
     
+    /// Alternative spellings from this map are resolved to their canonical team
+    pub fn set_alias_map(&mut self, alias_map: AliasMap) {
+        self.alias_map = alias_map;
+    }
+    
+    /// Recursively finds all files below the root directory
     pub fn init(&mut self) -> Result<(), io::Error> {
-        let mut root_path_list: Vec<PathBuf> = Vec::new();
+        // Failing to read the root directory itself is fatal
+        fs::read_dir(&self.root_dir)?;
+        
+        let root_dir = self.root_dir.clone();
+        let found_files = self.explore_dir(&root_dir);
+        let relative_files: Vec<PathBuf> = found_files.iter()
+            .map(|f| f.strip_prefix(&root_dir).unwrap_or(f).to_path_buf())
+            .collect();
         
-        let entries = fs::read_dir(Path::new("/tmp/football.json"))?;
-        for entry in entries {
-            let dir_entry = entry?;
-            let dir_entry_file_type = dir_entry.file_type()?;
+        self.select_files(&relative_files);
+        self.is_initialized = true;
+        Ok(())
+    }
+    
+    /// Initializes the loader with a specific list of files instead of exploring the root
+    /// directory. Paths are relative to the root directory. Used for incremental loads.
+    /// Every clubs file is selected as well, so that teams declared in several files get the
+    /// same source file as in a full load.
+    pub fn init_with_files(&mut self, files: &[PathBuf]) {
+        let root_dir = self.root_dir.clone();
+        let clubs_files: Vec<PathBuf> = self.explore_dir(&root_dir).into_iter()
+            .filter(|f| classify_file(f) == FileKind::Clubs)
+            .map(|f| f.strip_prefix(&root_dir).unwrap_or(&f).to_path_buf())
+            .collect();
+        
+        let mut sorted_files = files.to_vec();
+        sorted_files.extend(clubs_files);
+        sorted_files.sort();
+        sorted_files.dedup();
+        
+        self.select_files(&sorted_files);
+        self.is_initialized = true;
+    }
+    
+    /// Runs the files through the file filter and stores the selected ones
+    fn select_files(&mut self, relative_files: &[PathBuf]) {
+        for file in relative_files {
+            let selection = self.file_filter.select(file);
             
-            if !dir_entry_file_type.is_dir() || dir_entry.path().ends_with(".git") {
-                continue;
+            if selection.selected {
+                let path = self.root_dir.join(file);
+                let kind = classify_file(&path);
+                info!("Selected File: {:?} as {} ({})", file, kind, selection.reason);
+                
+                // League IDs are known up front, so that leagues can be streamed without changing the loader
+                match kind {
+                    FileKind::League => {
+                        self.league_ids.allocate(&file.to_string_lossy());
+                    },
+                    FileKind::Unknown => {
+                        self.report.record(Some(file.to_string_lossy().to_string()), None, IngestError::UnknownFileKind);
+                    },
+                    _ => (),
+                };
+                
+                self.files.push((path, kind));
+            } else {
+                info!("Skipped File: {:?} ({})", file, selection.reason);
             }
             
-            let entry_path = dir_entry.path();
-            info!("Found Directory: {:?}", entry_path);
-            root_path_list.push(entry_path);
+            self.file_selections.push(selection);
         }
+    }
+    
+    /// Returns all selected files along with what kind of file they are
+    pub fn get_files(&self) -> &Vec<(PathBuf, FileKind)> {
+        &self.files
+    }
+    
+    /// Returns all selected files of the given kind
+    pub fn get_files_of_kind(&self, kind: FileKind) -> Vec<&Path> {
+        self.files.iter()
+            .filter(|(_, k)| *k == kind)
+            .map(|(p, _)| p.as_path())
+            .collect()
+    }
+    
+    /// Returns every file that was considered while initializing, and why it was or was not selected
+    pub fn get_file_selections(&self) -> &Vec<FileSelection> {
+        &self.file_selections
+    }
+    
+    /// Makes a team that already exists in the database known to the loader, so that
+    /// matches can refer to it and newly loaded teams do not reuse its ID.
+    pub fn register_team(&mut self, team: Team) {
+        self.team_ids.reserve(team.id, &team.name);
+        self.add_team_name(Arc::new(team));
+    }
+    
+    /// Makes an alias that already exists in the database known to the loader
+    pub fn register_alias(&mut self, alias: &str, team_id: i32) {
+        let team = self.team_name_map.values().find(|t| t.id == team_id).cloned();
         
-        self.load_files(&root_path_list);
-        self.is_initialized = true;
-        Ok(())
+        match team {
+            Some(t) => {
+                self.team_name_map.insert(alias.to_string(), t);
+            },
+            None => warn!("Alias {} refers to unknown team: {}", alias, team_id),
+        };
     }
     
-    /// Stores all file paths found in all sub-directories into an internal vector
-    fn load_files(&mut self, path_list: &Vec<PathBuf>) -> &Vec<PathBuf> {
-        for path in path_list {
-            match self.explore_dir(path) {
-                Ok(mut directory_file_list) => self.files.append(&mut directory_file_list),
-                Err(e) => {
-                    warn!("Failed to explore: {:?} because: {}", path, e.to_string());
-                    continue;
-                }
-            }
+    /// Makes a team findable by its name and its normalized name
+    fn add_team_name(&mut self, team: Arc<Team>) {
+        let teams = self.normalized_team_map.entry(normalize_team_name(&team.name)).or_default();
+        if !teams.iter().any(|t| t.id == team.id) {
+            teams.push(team.clone());
         }
+        self.team_name_map.insert(team.name.clone(), team);
+    }
+    
+    /// Returns the teams whose name is the same as the given one after normalization
+    fn find_teams_by_normalized_name(&self, name: &str) -> &[Arc<Team>] {
+        self.normalized_team_map.get(&normalize_team_name(name))
+            .map(|teams| teams.as_slice())
+            .unwrap_or_default()
+    }
+    
+    /// Finds a team by its normalized name. Returns None if no team or more than one team has that normalized name.
+    fn find_team_by_normalized_name(&self, name: &str) -> Option<Arc<Team>> {
+        match self.find_teams_by_normalized_name(name) {
+            [team] => Some(team.clone()),
+            _ => None,
+        }
+    }
+    
+    /// Finds the team an alias map entry points to. With `exact`, only an alias spelled the same way counts.
+    fn find_team_in_alias_map(&self, name: &str, exact: bool) -> Option<Arc<Team>> {
+        let canonical = match exact {
+            true => self.alias_map.get_canonical_name_as_spelled(name),
+            false => self.alias_map.get_canonical_name(name),
+        }?;
         
-        return &self.files;
+        if canonical == name {
+            return None;
+        }
+        
+        self.team_name_map.get(canonical).cloned()
+            .or_else(|| self.find_team_by_normalized_name(canonical))
     }
-
+    
+    /// Resolves a spelling of a team name through the alias map and, if `normalize` is set, its normalized name.
+    /// Remembers it as an alias of the team. Returns false if the name can't be resolved.
+    fn resolve_alias(&mut self, name: &str, normalize: bool) -> bool {
+        if self.team_name_map.contains_key(name) {
+            return true;
+        }
+        
+        let found = match self.find_team_in_alias_map(name, !normalize) {
+            Some(team) => Some((team, AliasSource::AliasFile)),
+            None if normalize => self.find_team_by_normalized_name(name).map(|t| (t, AliasSource::Normalized)),
+            None => None,
+        };
+        
+        match found {
+            Some((team, source)) => {
+                self.add_alias(name, team, source);
+                true
+            },
+            None => false
+        }
+    }
+    
+    /// Remembers a spelling of a team name as an alias of the team
+    fn add_alias(&mut self, name: &str, team: Arc<Team>, source: AliasSource) {
+        info!("Resolved {} to {} ({})", name, team.name, source.as_str());
+        self.team_name_map.insert(name.to_string(), team.clone());
+        self.aliases.push((name.to_string(), team, source));
+    }
+    
+    /// Returns up to `limit` known team names similar to the given one, most similar first
+    pub fn suggest_team_names(&self, name: &str, limit: usize) -> Vec<(String, f64)> {
+        let mut suggestions: Vec<(String, f64)> = self.normalized_team_map.values().flatten()
+            .map(|t| (t.name.clone(), name_similarity(name, &t.name)))
+            .filter(|(_, similarity)| *similarity >= 0.5)
+            .collect();
+        
+        suggestions.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        suggestions.truncate(limit);
+        suggestions
+    }
+    
+    /// Marks the ID of a league already stored in the database as taken
+    pub fn reserve_league_id(&mut self, id: i32, source_file: &str) {
+        self.league_ids.reserve(id, source_file);
+    }
+    
+    /// Marks the ID of a group already stored in the database as taken
+    pub fn reserve_group_id(&mut self, id: i32, source_file: &str, name: &str) {
+        self.group_ids.reserve(id, &group_key(source_file, name));
+    }
+    
+    /// Returns the path of a file relative to the root directory
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+    
     /// Loads all teams from all files. Will return None if struct has not been initialized.
-    pub fn load_teams(&mut self) -> Option<&Vec<Rc<Team>>> {
+    pub fn load_teams(&mut self) -> Option<&Vec<Arc<Team>>> {
         if !self.is_initialized {
             warn!("JsonLoader not yet initialized. This function call will return nothing");
             return None;
         }
 
-        for file_path in &self.files {
-            if !file_path.to_string_lossy().contains("clubs") {
-                continue;
-            }
-
-            let file_content = match fs::read_to_string(file_path) {
-                Ok(f) => f,
-                Err(e) => {
-                    warn!("Failed to read: {} because: {}", file_path.to_string_lossy(), e.to_string());
-                    continue;
-                }
-            };
-            
-            let match_team_list: MatchTeamList = match serde_json::from_str(&file_content) {
+        let clubs_files: Vec<PathBuf> = self.get_files_of_kind(FileKind::Clubs).into_iter()
+            .map(|p| p.to_path_buf())
+            .collect();
+        
+        for (file_path, result) in parse_files::<MatchTeamList>(&clubs_files, self.jobs) {
+            let match_team_list = match result {
                 Ok(match_team) => match_team,
                 Err(e) => {
-                    warn!("Failed to deserialize: {} because: {}", file_path.to_string_lossy(), e.to_string());
+                    self.report.record(Some(self.relative_path(&file_path)), None, e);
                     continue;
                 }
             };
+            self.report.files_loaded += 1;
             
             for mut team in match_team_list.teams {
                 let mut found = false;
@@ -100,76 +436,212 @@ impl JsonLoader {
                     continue;
                 }
                 
-                team.id = self.teams.len() as i32 + 1;
+                // Declared teams only become aliases of another team through an alias map entry. Normalization
+                // is left to the names in matches and groups, so that e.g. Liverpool FC and Liverpool stay two teams.
+                if let Some(canonical_team) = self.find_team_in_alias_map(&team.name, true) {
+                    if self.team_name_map.get(&team.name).map(|t| t.id) != Some(canonical_team.id) {
+                        self.add_alias(&team.name, canonical_team, AliasSource::AliasFile);
+                    }
+                    continue;
+                }
                 
-                let team_rc = Rc::new(team);
+                // Teams that are already known keep their ID. An alias stored with the same name doesn't count.
+                team.id = match self.team_name_map.get(&team.name).filter(|t| t.name == team.name) {
+                    Some(existing) => existing.id,
+                    None => self.team_ids.allocate(&team.name),
+                };
+                team.source_file = self.relative_path(&file_path);
+                
+                let team_rc = Arc::new(team);
                 self.teams.push(team_rc.clone());
-                self.team_name_map.insert(team_rc.name.clone(), team_rc.clone());
+                self.add_team_name(team_rc);
             }
         }
         
-        return Some(self.get_teams());
+        // Every alias from the alias map is kept, whether it is used or not
+        let alias_file_names: Vec<String> = self.alias_map.get_entries().iter()
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        for alias in alias_file_names {
+            self.resolve_alias(&alias, false);
+        }
+        
+        Some(self.get_teams())
     }
     
-    pub fn load_leagues(&mut self) -> Option<&Vec<LeagueJsonFormat>> {
+    /// Loads all groups files. Groups are linked to the league from the league file next to them.
+    /// Otherwise, the DatabaseProcessor links them by name to a stored league. Will return None if struct has not been initialized.
+    pub fn load_groups(&mut self) -> Option<&Vec<GroupList>> {
         if !self.is_initialized {
             warn!("JsonLoader not yet initialized. This function call will return nothing");
             return None;
         }
         
-        for file_path in &self.files {
-            if file_path.to_string_lossy().contains("clubs") || file_path.to_string_lossy().contains("groups") {
-                continue;
-            }
-            
-            let file_content = match fs::read_to_string(file_path) {
-                Ok(f) => f,
+        let groups_files: Vec<PathBuf> = self.get_files_of_kind(FileKind::Groups).into_iter()
+            .map(|p| p.to_path_buf())
+            .collect();
+        
+        for (file_path, result) in parse_files::<GroupList>(&groups_files, self.jobs) {
+            let mut group_list = match result {
+                Ok(g) => g,
                 Err(e) => {
-                    warn!("Failed to read: {} because: {}", file_path.to_string_lossy(), e.to_string());
+                    self.report.record(Some(self.relative_path(&file_path)), None, e);
                     continue;
                 }
             };
+            self.report.files_loaded += 1;
             
-            let mut league: LeagueJsonFormat = match serde_json::from_str(&file_content) {
-                Ok(l) => l,
-                Err(e) => {
-                    warn!("Failed to deserialize: {} because: {}", file_path.to_string_lossy(), e.to_string());
-                    continue;
-                }
-            };
+            // Groups files without a league file next to them are linked by name once the leagues are inserted
+            group_list.league_id = companion_file(&file_path)
+                .and_then(|p| self.league_ids.get(&self.relative_path(&p)));
+            group_list.source_file = self.relative_path(&file_path);
+            
+            for group in group_list.groups.iter_mut() {
+                group.id = self.group_ids.allocate(&group_key(&group_list.source_file, &group.name));
+            }
+            
+            self.groups.push(group_list);
+        }
+        
+        Some(&self.groups)
+    }
 
-            league.set_league_id(self.leagues.len() as i32 + 1);
-            self.leagues.push(league);
+    /// Returns a stream of the selected league files, which reads the files as it is iterated instead of
+    /// keeping all leagues in memory. The stream doesn't borrow the loader, so teams can
+    /// be resolved with resolve_team_name while iterating.
+    pub fn stream_leagues(&self) -> LeagueStream {
+        if !self.is_initialized {
+            warn!("JsonLoader not yet initialized. The stream will be empty");
         }
         
-        Some(&self.leagues)
+        let files = self.get_files_of_kind(FileKind::League).into_iter()
+            .filter_map(|path| {
+                let source_file = self.relative_path(path);
+                let league_id = self.league_ids.get(&source_file)?;
+                Some(PendingLeagueFile { path: path.to_path_buf(), source_file, league_id })
+            })
+            .collect();
+        
+        LeagueStream::new(files, self.jobs)
+    }
+    
+    /// Resolves the name of a team taking part in a match or group: by its name, an alias or its normalized name.
+    /// Teams that can't be found are created if the policy is MissingTeamPolicy::AutoCreate. Otherwise, they are
+    /// remembered in get_unknown_team_names.
+    pub fn resolve_team_name(&mut self, name: &str, source_file: &str) -> TeamResolution {
+        if self.resolve_alias(name, true) {
+            return match self.team_name_map.get(name) {
+                Some(team) => TeamResolution::Known(team.id),
+                None => TeamResolution::Unknown,
+            };
+        }
+        
+        if self.missing_team_policy == MissingTeamPolicy::AutoCreate {
+            // A name that could be one of several teams gets a team of its own, but the candidates are shown
+            if self.find_teams_by_normalized_name(name).len() > 1 {
+                self.warn_unknown_team(name);
+            }
+            info!("Creating team missing from the clubs files: {}", name);
+            return TeamResolution::Created(self.create_inferred_team(name.to_string(), source_file.to_string()));
+        }
+        
+        if !self.unknown_team_names.iter().any(|n| n == name) {
+            self.warn_unknown_team(name);
+            self.unknown_team_names.push(name.to_string());
+        }
+        
+        TeamResolution::Unknown
+    }
+    
+    /// Warns about an unknown team, suggesting known teams with a similar name
+    fn warn_unknown_team(&self, name: &str) {
+        let suggestions: Vec<String> = self.suggest_team_names(name, 3).into_iter()
+            .map(|(n, similarity)| format!("{} ({:.2})", n, similarity))
+            .collect();
+        
+        if !suggestions.is_empty() {
+            warn!("Unknown team: {}. Did you mean: {}", name, suggestions.join(", "));
+        }
+    }
+    
+    /// Returns the names that resolve_team_name could not find a team for, in the order they were found
+    pub fn get_unknown_team_names(&self) -> &Vec<String> {
+        &self.unknown_team_names
+    }
+    
+    /// Creates a team that is missing from the clubs files, marked as inferred
+    fn create_inferred_team(&mut self, name: String, source_file: String) -> Arc<Team> {
+        let team = Team {
+            id: self.team_ids.allocate(&name),
+            name,
+            code: None,
+            country: None,
+            source_file,
+            origin: TeamOrigin::Inferred,
+        };
+        
+        let team_rc = Arc::new(team);
+        self.teams.push(team_rc.clone());
+        self.add_team_name(team_rc.clone());
+        team_rc
     }
 
-    /// Reads all items in a directory
-    fn explore_dir(&self, path: &PathBuf) -> Result<Vec<PathBuf>, io::Error> {
+    /// Reads all files in a directory and its sub-directories. Hidden directories such as .git are skipped.
+    fn explore_dir(&self, path: &Path) -> Vec<PathBuf> {
         info!("Exploring: {}", path.to_str().unwrap_or_default());
         let mut file_path_list: Vec<PathBuf> = Vec::new();
-        let dir_items = fs::read_dir(path)?;
+        let dir_items = match fs::read_dir(path) {
+            Ok(items) => items,
+            Err(e) => {
+                warn!("Failed to explore: {:?} because: {}", path, e.to_string());
+                return file_path_list;
+            }
+        };
 
+        // read_dir order depends on the file system. Sorting keeps loads reproducible.
+        let mut entry_paths: Vec<PathBuf> = Vec::new();
         for entry in dir_items {
-            file_path_list.push(entry?.path());
+            match entry {
+                Ok(e) => entry_paths.push(e.path()),
+                Err(e) => warn!("Failed to explore: {:?} because: {}", path, e.to_string()),
+            };
+        }
+        entry_paths.sort();
+
+        for entry_path in entry_paths {
+            
+            if entry_path.is_dir() {
+                if entry_path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) {
+                    continue;
+                }
+                
+                file_path_list.append(&mut self.explore_dir(&entry_path));
+            } else {
+                file_path_list.push(entry_path);
+            }
         }
         
-        return Ok(file_path_list);
+        file_path_list
     }
     
-    pub fn get_teams(&self) -> &Vec<Rc<Team>> {
+    pub fn get_teams(&self) -> &Vec<Arc<Team>> {
         &self.teams
     }
     
-    pub fn get_leagues(&self) -> &Vec<LeagueJsonFormat> {
-        &self.leagues
+    pub fn get_aliases(&self) -> &Vec<(String, Arc<Team>, AliasSource)> {
+        &self.aliases
+    }
+    
+    /// Returns the files and records skipped while loading
+    pub fn get_report(&self) -> &IngestReport {
+        &self.report
+    }
+    
+    pub fn get_groups(&self) -> &Vec<GroupList> {
+        &self.groups
     }
     
     pub fn get_team_id_from_name(&self, name: &String) -> Option<i32> {
-        match self.team_name_map.get(name) {
-            Some(t) => Some(t.id),
-            None => None
-        }
+        self.team_name_map.get(name).map(|t| t.id)
     }
 }
\ No newline at end of file
@@ -1,3 +1,15 @@
 pub mod model;
 pub mod json;
-pub mod db;
\ No newline at end of file
+pub mod stream;
+pub mod filter;
+pub mod classify;
+pub mod competition;
+pub mod country;
+pub mod alias;
+pub mod id;
+pub mod report;
+pub mod standings;
+pub mod rating;
+pub mod migration;
+pub mod db;
+pub mod source;
\ No newline at end of file
@@ -1,15 +1,70 @@
-use std::{process::exit, path::{Path, self}};
+use std::{process::exit, path::PathBuf};
 
-use argparse::{ArgumentParser, Store, StoreTrue, StoreOption};
+use argparse::{ArgumentParser, Collect, Store, StoreTrue, StoreOption};
 use env_logger::{Builder, Env};
-use football_json_loader::{json::JsonLoader, db::DatabaseProcessor, model::{LeagueJsonFormat, LeagueMatch}};
-use git2::Repository;
+use football_json_loader::{json::{JsonLoader, MissingTeamPolicy, MissingTeamsError}, alias::AliasMap, filter::FileFilter, db::{DatabaseProcessor, SOURCE_COMMIT_KEY, SOURCE_URL_KEY}, source::{SourceManager, DEFAULT_REMOTE_URL}, report::{IngestErrorCategory, ReportFormat}, rating::EloSettings, standings::{parse_tie_breakers, PointsRule, StandingsRules}};
 use log::{info, warn};
 
+/// Exit codes, so that scripts can tell what went wrong. argparse exits with 2 on invalid arguments.
+#[derive(Clone, Copy)]
+enum Failure {
+    /// Invalid options, e.g. file patterns or the alias file
+    Config = 1,
+    /// football.json could not be cloned, fetched or read
+    Source = 3,
+    /// Files could not be read or deserialized
+    Parse = 4,
+    /// The database could not be opened or written to
+    Database = 5,
+    /// The data refers to unknown teams or leagues
+    DataQuality = 6,
+}
+
+impl From<IngestErrorCategory> for Failure {
+    fn from(category: IngestErrorCategory) -> Self {
+        match category {
+            IngestErrorCategory::Parse => Failure::Parse,
+            IngestErrorCategory::Database => Failure::Database,
+            IngestErrorCategory::DataQuality => Failure::DataQuality,
+        }
+    }
+}
+
+fn fail(failure: Failure) -> ! {
+    exit(failure as i32)
+}
+
+/// Rolls back everything written by this load before failing
+fn abort(db_processor: &DatabaseProcessor, failure: Failure) -> ! {
+    if let Err(e) = db_processor.rollback_transaction() {
+        warn!("Failed to roll back the database: {}", e);
+    }
+    
+    fail(failure)
+}
+
 fn main() {
     let mut db_loc: Option<String> = None;
     let mut warning = false;
     let mut verbose = false;
+    let mut source_dir: Option<String> = None;
+    let mut cache_dir = String::from("/tmp");
+    let mut remote_url = String::from(DEFAULT_REMOTE_URL);
+    let mut revision: Option<String> = None;
+    let mut update = false;
+    let mut incremental = false;
+    let mut include: Vec<String> = Vec::new();
+    let mut exclude: Vec<String> = Vec::new();
+    let mut list_files = false;
+    let mut missing_team_policy = MissingTeamPolicy::Skip;
+    let mut alias_file: Option<String> = None;
+    let mut report_format: Option<ReportFormat> = None;
+    let mut strict = false;
+    let mut batch_size: usize = 0;
+    let mut jobs: usize = 0;
+    let mut points = PointsRule::default();
+    let mut tie_breakers = String::from("gd,gf");
+    let mut elo_settings = EloSettings::default();
 
     {
         let mut arg_parser = ArgumentParser::new();
@@ -22,6 +77,69 @@ fn main() {
 
         arg_parser.refer(&mut db_loc)
             .add_option(&["-f", "--file_loc"], StoreOption, "Where to save the SQLite Database");
+
+        arg_parser.refer(&mut source_dir)
+            .add_option(&["-s", "--source-dir"], StoreOption, "Use an existing football.json checkout instead of cloning one");
+
+        arg_parser.refer(&mut cache_dir)
+            .add_option(&["-c", "--cache-dir"], Store, "Where to clone football.json into (Default: /tmp)");
+
+        arg_parser.refer(&mut remote_url)
+            .add_option(&["--remote-url"], Store, "Where to clone/fetch football.json from. Accepts file:// mirrors");
+
+        arg_parser.refer(&mut revision)
+            .add_option(&["-r", "--revision"], StoreOption, "Tag, branch or commit of football.json to check out");
+
+        arg_parser.refer(&mut update)
+            .add_option(&["-u", "--update"], StoreTrue, "Fetch and fast-forward an existing copy of football.json");
+
+        arg_parser.refer(&mut incremental)
+            .add_option(&["-i", "--incremental"], StoreTrue, "Only reload the files that changed since the commit recorded in the database");
+
+        arg_parser.refer(&mut include)
+            .add_option(&["--include"], Collect, "Only load files matching this glob pattern. Patterns starting with ! exclude files. Can be repeated");
+
+        arg_parser.refer(&mut exclude)
+            .add_option(&["--exclude"], Collect, "Do not load files matching this glob pattern. Can be repeated");
+
+        arg_parser.refer(&mut list_files)
+            .add_option(&["--list-files"], StoreTrue, "List which files would be loaded and why, then exit");
+
+        arg_parser.refer(&mut missing_team_policy)
+            .add_option(&["--missing-teams"], Store, "What to do with teams missing from the clubs files: skip, create or fail (Default: skip)");
+
+        arg_parser.refer(&mut alias_file)
+            .add_option(&["-a", "--aliases"], StoreOption, "JSON file mapping team names to their other spellings");
+
+        arg_parser.refer(&mut report_format)
+            .add_option(&["--report"], StoreOption, "Print a report of what was loaded and skipped: human or json");
+
+        arg_parser.refer(&mut strict)
+            .add_option(&["--strict"], StoreTrue, "Fail if any file, match or group had to be skipped");
+
+        arg_parser.refer(&mut batch_size)
+            .add_option(&["--batch-size"], Store, "Commit after this many rows. 0 commits the whole load at once, so a failed load changes nothing (Default: 0)");
+
+        arg_parser.refer(&mut jobs)
+            .add_option(&["-j", "--jobs"], Store, "Number of threads reading and parsing files. 0 uses one per CPU (Default: 0)");
+
+        arg_parser.refer(&mut points)
+            .add_option(&["--points"], Store, "Points for a win, draw and loss in the standings table (Default: 3/1/0)");
+
+        arg_parser.refer(&mut tie_breakers)
+            .add_option(&["--tie-breakers"], Store, "Comma separated tie-breakers for teams level on points: gd, gf, wins, h2h, h2h-gd, h2h-gf (Default: gd,gf)");
+
+        arg_parser.refer(&mut elo_settings.k_factor)
+            .add_option(&["--elo-k-factor"], Store, "Largest change of a team's Elo rating from a single match (Default: 20)");
+
+        arg_parser.refer(&mut elo_settings.home_advantage)
+            .add_option(&["--elo-home-advantage"], Store, "Elo rating points added to the home team when working out the expected result (Default: 100)");
+
+        arg_parser.refer(&mut elo_settings.margin_of_victory)
+            .add_option(&["--elo-margin"], Store, "How the size of a win scales the Elo rating change: off, world-football or log (Default: world-football)");
+
+        arg_parser.refer(&mut elo_settings.season_regression)
+            .add_option(&["--elo-regression"], Store, "Share of the distance to 1500 a team's Elo rating moves back by each season, from 0 to 1 (Default: 0)");
         
         arg_parser.parse_args_or_exit();
     }
@@ -36,79 +154,104 @@ fn main() {
     }
     info!("Initialized Logger");
     
-    // Checks if we have already cloned football.json. If not, clone it into /tmp
-    let repo_path = Path::new("/tmp/football.json");
-    if repo_path.exists() {
-        info!("Using a cached copy of football.json...");
+    // Use the given checkout if there is one. Otherwise, clone football.json into the cache directory
+    let repo_path = match &source_dir {
+        Some(dir) => {
+            let path = PathBuf::from(dir);
+            if !path.is_dir() {
+                warn!("Source directory does not exist: {}", path.to_string_lossy());
+                fail(Failure::Source);
+            }
+            
+            info!("Using football.json from: {}", path.to_string_lossy());
+            path
+        },
+        None => PathBuf::from(&cache_dir).join("football.json")
+    };
+    
+    let source_manager = SourceManager::new(&repo_path)
+        .remote_url(&remote_url)
+        .revision(revision.clone());
+    
+    // A local checkout is only touched when explicitly asked to. It does not need to be a git repository.
+    let use_checkout_as_is = source_dir.is_some() && !update && revision.is_none();
+    let source_commit = if use_checkout_as_is {
+        match source_manager.current_commit() {
+            Ok(sha) => Some(sha),
+            Err(e) => {
+                warn!("Could not determine the football.json commit: {}", e);
+                None
+            }
+        }
     } else {
-        let url = "https://github.com/openfootball/football.json.git";
-        
-        if let Err(e) = Repository::clone(url, "/tmp/football.json") {
-            warn!("Failed to clone football.json: {}", e);
-            exit(-1);
+        match source_manager.sync(update) {
+            Ok(sha) => Some(sha),
+            Err(e) => {
+                warn!("Failed to prepare football.json: {}", e);
+                fail(Failure::Source);
+            }
         }
-    }
+    };
     
-    let mut json_loader = JsonLoader::new();
-    if let Err(e) = json_loader.init() {
-        warn!("Failed to initialize JsonLoader: {}", e.to_string());
-        exit(-1);
+    if let Some(sha) = &source_commit {
+        info!("Loading football.json at commit: {}", sha);
     }
     
-    let _teams = match json_loader.load_teams() {
-        Some(t) => t,
-        None => {
-            warn!("JsonLoader not yet initialized");
-            exit(-1);
+    let file_filter = match FileFilter::new(&include, &exclude) {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("Invalid file pattern: {}", e);
+            fail(Failure::Config);
         }
     };
     
-    // for t in teams.iter() {
-    //     println!("{} {}", t.id, t.name);
-    // }
-
-    let _leagues = match json_loader.load_leagues() {
-        Some(l) => l,
-        None => {
-            warn!("JsonLoader not yet initialized");
-            exit(-1);
+    let standings_rules = match parse_tie_breakers(&tie_breakers) {
+        Ok(t) => StandingsRules::new(points, t),
+        Err(e) => {
+            warn!("{}", e);
+            fail(Failure::Config);
         }
     };
     
-    // for league in leagues {
-    //     match league {
-    //         LeagueJsonFormat::OldFormat(old_league) => {
-    //             for round in old_league.rounds.iter() {
-    //                 for m in round.matches.iter() {
-    //                     println!(
-    //                         "{} Match: {}    {} v {} Score: {}:{}",
-    //                         old_league.name,
-    //                         m.get_date(),
-    //                         m.get_team_1_name(),
-    //                         m.get_team_2_name(),
-    //                         m.get_team_1_score().unwrap_or(-1),
-    //                         m.get_team_2_score().unwrap_or(-1)
-    //                     );
-    //                 }
-    //             }
-    //         },
-    //         LeagueJsonFormat::NewFormat(new_league) => {
-    //             for m in new_league.matches.iter() {
-    //                 println!(
-    //                     "{} Match: {}    {} v {} Score: {}:{}",
-    //                     new_league.name,
-    //                     m.get_date(),
-    //                     m.get_team_1_name(),
-    //                     m.get_team_2_name(),
-    //                     m.get_team_1_score().unwrap_or(-1),
-    //                     m.get_team_2_score().unwrap_or(-1)
-    //                 );
-    //             }
-    //         }
-    //     }
-    // }
-    
-    // Saving the result to an SQLite database
+    if let Err(e) = elo_settings.validate() {
+        warn!("{}", e);
+        fail(Failure::Config);
+    }
+    
+    let mut json_loader = JsonLoader::with_root_dir(&repo_path);
+    json_loader.set_file_filter(file_filter);
+    json_loader.set_missing_team_policy(missing_team_policy);
+    json_loader.set_jobs(jobs);
+    
+    if let Some(path) = &alias_file {
+        match AliasMap::load(path) {
+            Ok(alias_map) => json_loader.set_alias_map(alias_map),
+            Err(e) => {
+                warn!("Failed to load aliases from {}: {}", path, e);
+                fail(Failure::Config);
+            }
+        };
+    }
+    
+    if list_files {
+        if let Err(e) = json_loader.init() {
+            warn!("Failed to initialize JsonLoader: {}", e);
+            fail(Failure::Source);
+        }
+        
+        for selection in json_loader.get_file_selections() {
+            let status = if selection.selected { "selected" } else { "skipped" };
+            let kind = json_loader.get_files().iter()
+                .find(|(p, _)| p.ends_with(&selection.path))
+                .map(|(_, k)| k.to_string())
+                .unwrap_or_default();
+            println!("{:<8} {:<7} {}  ({})", status, kind, selection.path.to_string_lossy(), selection.reason);
+        }
+        
+        return;
+    }
+    
+    // Opening the SQLite database the result will be saved to
     let mut db_processor = DatabaseProcessor::new();
     let db_init_result =  match db_loc {
         Some(path) => db_processor.init(&path),
@@ -117,10 +260,183 @@ fn main() {
     
     if let Err(e) = db_init_result {
         warn!("Failed to initialize database: {}", e.to_string());
+        fail(Failure::Database);
+    }
+    db_processor.set_batch_size(batch_size);
+    
+    // Work out which files need to be (re-)loaded
+    let mut deleted_files: Vec<String> = Vec::new();
+    let previous_commit = if incremental {
+        match db_processor.get_metadata(SOURCE_COMMIT_KEY) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Failed to read the previously loaded commit: {}", e);
+                fail(Failure::Database);
+            }
+        }
+    } else {
+        None
+    };
+    
+    if previous_commit.is_some() && previous_commit == source_commit {
+        info!("Database is already up to date with commit: {}", source_commit.unwrap_or_default());
+        return;
     }
     
-    if let Err(e) = db_processor.insert_data_from_loader(&json_loader) {
-        warn!("Failed to insert data into SQLite database: {}", e.to_string());
+    // Everything from here on is written in one transaction (or in batches), so that a failed load can be undone
+    if let Err(e) = db_processor.begin_transaction() {
+        warn!("Failed to start a transaction: {}", e);
+        fail(Failure::Database);
+    }
+    
+    match (&previous_commit, &source_commit) {
+        (Some(previous), Some(_)) => {
+            
+            let mut changes = match source_manager.changed_files(previous) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("Failed to diff football.json against commit {}: {}", previous, e);
+                    abort(&db_processor, Failure::Source);
+                }
+            };
+            
+            changes.add_companion_files(&repo_path);
+            
+            // Modified files are removed and then loaded again
+            let mut stale_files: Vec<String> = changes.changed.iter()
+                .chain(changes.deleted.iter())
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            
+            // Groups files linked to a league by name lose their groups along with the league, so they are loaded again too
+            let linked_files = match db_processor.get_linked_groups_files(&stale_files) {
+                Ok(f) => f,
+                Err(e) => {
+                    warn!("Failed to find the groups files of outdated leagues: {}", e);
+                    abort(&db_processor, Failure::Database);
+                }
+            };
+            for linked_file in linked_files {
+                let path = PathBuf::from(&linked_file);
+                if repo_path.join(&path).is_file() {
+                    changes.changed.push(path);
+                }
+                stale_files.push(linked_file);
+            }
+            stale_files.sort();
+            
+            if let Err(e) = db_processor.remove_leagues_from_files(&stale_files) {
+                warn!("Failed to remove outdated leagues: {}", e);
+                abort(&db_processor, Failure::Database);
+            }
+            
+            if let Err(e) = db_processor.prepare_incremental_load(&mut json_loader) {
+                warn!("Failed to read existing teams from the database: {}", e);
+                abort(&db_processor, Failure::Database);
+            }
+            
+            deleted_files = changes.deleted.iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            json_loader.init_with_files(&changes.changed);
+        },
+        _ => {
+            if incremental {
+                warn!("No previously loaded commit recorded. Performing a full load");
+            }
+            
+            // A full load into an existing database keeps the IDs already stored there
+            if let Err(e) = db_processor.prepare_incremental_load(&mut json_loader) {
+                warn!("Failed to read existing teams from the database: {}", e);
+                abort(&db_processor, Failure::Database);
+            }
+            
+            if let Err(e) = json_loader.init() {
+                warn!("Failed to initialize JsonLoader: {}", e);
+                abort(&db_processor, Failure::Source);
+            }
+        }
+    };
+    
+    if json_loader.load_teams().is_none() {
+        warn!("JsonLoader not yet initialized");
+        abort(&db_processor, Failure::Parse);
+    }
+    
+    if json_loader.load_groups().is_none() {
+        warn!("JsonLoader not yet initialized");
+        abort(&db_processor, Failure::Parse);
+    }
+    
+    // Saving the result to the SQLite database. League files are read one at a time while inserting.
+    let insert_report = match db_processor.insert_data_streaming(&mut json_loader) {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Failed to insert data into SQLite database: {}", e.to_string());
+            abort(&db_processor, Failure::Database);
+        }
+    };
+    let mut report = json_loader.get_report().clone();
+    report.merge(insert_report);
+    
+    let unknown_teams = json_loader.get_unknown_team_names();
+    if missing_team_policy == MissingTeamPolicy::Fail && !unknown_teams.is_empty() {
+        warn!("{}", MissingTeamsError { names: unknown_teams.clone() });
+        abort(&db_processor, Failure::DataQuality);
+    }
+    
+    // Standings are computed from all matches in the database, so they are rewritten whole
+    if let Err(e) = db_processor.write_standings(&standings_rules) {
+        warn!("Failed to compute standings: {}", e);
+        abort(&db_processor, Failure::Database);
+    }
+    
+    // Ratings depend on every match played before, so they are rewritten whole as well
+    if let Err(e) = db_processor.write_ratings(elo_settings) {
+        warn!("Failed to compute ratings: {}", e);
+        abort(&db_processor, Failure::Database);
+    }
+    
+    // Teams are only removed once nothing refers to them anymore
+    if let Err(e) = db_processor.remove_teams_from_files(&deleted_files) {
+        warn!("Failed to remove teams from deleted files: {}", e);
+        abort(&db_processor, Failure::Database);
+    }
+    
+    match report_format {
+        Some(ReportFormat::Human) => print!("{}", report),
+        Some(ReportFormat::Json) => match report.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => warn!("Failed to serialize the report: {}", e),
+        },
+        None => (),
+    };
+    
+    // A strict load that had to skip anything is rolled back, so the source commit isn't recorded either
+    if strict {
+        if let Some(category) = report.worst_category() {
+            warn!("{} files or records were skipped", report.entries.len());
+            abort(&db_processor, category.into());
+        }
+    }
+    
+    // Record where the data came from so the database can be traced back to an upstream revision
+    if let Some(sha) = &source_commit {
+        if let Err(e) = db_processor.set_metadata(SOURCE_COMMIT_KEY, sha) {
+            warn!("Failed to record the source commit: {}", e);
+            abort(&db_processor, Failure::Database);
+        }
+        
+        if !use_checkout_as_is {
+            if let Err(e) = db_processor.set_metadata(SOURCE_URL_KEY, source_manager.get_remote_url()) {
+                warn!("Failed to record the source URL: {}", e);
+                abort(&db_processor, Failure::Database);
+            }
+        }
+    }
+    
+    if let Err(e) = db_processor.commit_transaction() {
+        warn!("Failed to commit the load: {}", e);
+        abort(&db_processor, Failure::Database);
     }
-
 }
@@ -0,0 +1,475 @@
+use std::fmt;
+
+use log::info;
+use rusqlite::Connection;
+
+/// A step in the history of the database schema. Migrations are applied in order, each exactly once.
+pub struct Migration {
+    /// Schema version of the database once the migration is applied
+    pub version: i32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every version of the schema so far. Released databases must keep working, so existing
+/// migrations are never changed. Schema changes are made by adding a new migration.
+pub const MIGRATIONS: [Migration; 8] = [
+    Migration {
+        version: 1,
+        description: "Teams, leagues and matches",
+        sql: "
+            CREATE TABLE IF NOT EXISTS teams (
+                id      INTEGER PRIMARY KEY,
+                name    TEXT    NOT NULL,
+                code    TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS leagues (
+                id      INTEGER PRIMARY KEY,
+                name    TEXT    NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS matches (
+                id          INTEGER PRIMARY KEY,
+                date        TEXT NOT NULL,
+                league_id   INTEGER NOT NULL REFERENCES leagues(id),
+                team1_id    INTEGER NOT NULL REFERENCES teams(id),
+                team2_id    INTEGER NOT NULL REFERENCES teams(id),
+                stage       TEXT,
+                round       TEXT,
+                team1_score INTEGER,
+                team2_score INTEGER
+            );
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "Source files, countries, competitions, seasons, venues, groups, goals, aliases and metadata",
+        sql: "
+            CREATE TABLE countries (
+                id          INTEGER PRIMARY KEY,
+                code        TEXT    NOT NULL UNIQUE,
+                name        TEXT    NOT NULL
+            );
+
+            ALTER TABLE teams ADD COLUMN country_id  INTEGER REFERENCES countries(id);
+            ALTER TABLE teams ADD COLUMN origin      TEXT    NOT NULL DEFAULT 'declared';
+            ALTER TABLE teams ADD COLUMN source_file TEXT;
+
+            CREATE TABLE team_aliases (
+                alias       TEXT    PRIMARY KEY,
+                team_id     INTEGER NOT NULL REFERENCES teams(id),
+                source      TEXT    NOT NULL
+            );
+
+            CREATE TABLE competitions (
+                id          INTEGER PRIMARY KEY,
+                code        TEXT    NOT NULL UNIQUE,
+                name        TEXT,
+                country_id  INTEGER REFERENCES countries(id),
+                tier        INTEGER
+            );
+
+            CREATE TABLE seasons (
+                id          INTEGER PRIMARY KEY,
+                name        TEXT    NOT NULL UNIQUE,
+                start_year  INTEGER NOT NULL,
+                end_year    INTEGER NOT NULL
+            );
+
+            ALTER TABLE leagues ADD COLUMN competition_id  INTEGER REFERENCES competitions(id);
+            ALTER TABLE leagues ADD COLUMN season_id       INTEGER REFERENCES seasons(id);
+            ALTER TABLE leagues ADD COLUMN source_file     TEXT;
+
+            CREATE TABLE venues (
+                id          INTEGER PRIMARY KEY,
+                name        TEXT    NOT NULL UNIQUE
+            );
+
+            ALTER TABLE matches ADD COLUMN kickoff            TEXT;
+            ALTER TABLE matches ADD COLUMN kickoff_utc_offset TEXT;
+            ALTER TABLE matches ADD COLUMN venue_id           INTEGER REFERENCES venues(id);
+            ALTER TABLE matches ADD COLUMN team1_score_ht     INTEGER;
+            ALTER TABLE matches ADD COLUMN team2_score_ht     INTEGER;
+            ALTER TABLE matches ADD COLUMN team1_score_ft     INTEGER;
+            ALTER TABLE matches ADD COLUMN team2_score_ft     INTEGER;
+            ALTER TABLE matches ADD COLUMN team1_score_et     INTEGER;
+            ALTER TABLE matches ADD COLUMN team2_score_et     INTEGER;
+            ALTER TABLE matches ADD COLUMN team1_score_p      INTEGER;
+            ALTER TABLE matches ADD COLUMN team2_score_p      INTEGER;
+            ALTER TABLE matches ADD COLUMN team1_score_agg    INTEGER;
+            ALTER TABLE matches ADD COLUMN team2_score_agg    INTEGER;
+
+            CREATE TABLE groups (
+                id          INTEGER PRIMARY KEY,
+                league_id   INTEGER NOT NULL REFERENCES leagues(id),
+                name        TEXT    NOT NULL,
+                source_file TEXT
+            );
+
+            CREATE TABLE group_teams (
+                group_id    INTEGER NOT NULL REFERENCES groups(id),
+                team_id     INTEGER NOT NULL REFERENCES teams(id),
+                PRIMARY KEY (group_id, team_id)
+            );
+
+            CREATE TABLE players (
+                id          INTEGER PRIMARY KEY,
+                name        TEXT    NOT NULL,
+                team_id     INTEGER NOT NULL REFERENCES teams(id),
+                UNIQUE (name, team_id)
+            );
+
+            CREATE TABLE goals (
+                id              INTEGER PRIMARY KEY,
+                match_id        INTEGER NOT NULL REFERENCES matches(id),
+                team_id         INTEGER NOT NULL REFERENCES teams(id),
+                player_id       INTEGER NOT NULL REFERENCES players(id),
+                minute          INTEGER NOT NULL,
+                minute_offset   INTEGER,
+                penalty         INTEGER NOT NULL,
+                owngoal         INTEGER NOT NULL
+            );
+
+            CREATE TABLE metadata (
+                key     TEXT PRIMARY KEY,
+                value   TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "Unique natural keys of teams, leagues and matches",
+        sql: "
+            -- Leagues and matches inserted more than once by earlier versions are merged into the first copy
+            UPDATE matches SET league_id = (
+                SELECT MIN(duplicate.id) FROM leagues
+                JOIN leagues AS duplicate ON duplicate.competition_id = leagues.competition_id
+                    AND duplicate.season_id = leagues.season_id
+                WHERE leagues.id = matches.league_id
+            )
+            WHERE league_id IN (SELECT id FROM leagues WHERE competition_id IS NOT NULL AND season_id IS NOT NULL);
+
+            UPDATE groups SET league_id = (
+                SELECT MIN(duplicate.id) FROM leagues
+                JOIN leagues AS duplicate ON duplicate.competition_id = leagues.competition_id
+                    AND duplicate.season_id = leagues.season_id
+                WHERE leagues.id = groups.league_id
+            )
+            WHERE league_id IN (SELECT id FROM leagues WHERE competition_id IS NOT NULL AND season_id IS NOT NULL);
+
+            DELETE FROM leagues
+            WHERE competition_id IS NOT NULL AND season_id IS NOT NULL
+            AND id NOT IN (SELECT MIN(id) FROM leagues GROUP BY competition_id, season_id);
+
+            DELETE FROM goals
+            WHERE match_id NOT IN (SELECT MIN(id) FROM matches GROUP BY league_id, date, team1_id, team2_id);
+
+            DELETE FROM matches
+            WHERE id NOT IN (SELECT MIN(id) FROM matches GROUP BY league_id, date, team1_id, team2_id);
+
+            CREATE UNIQUE INDEX teams_name ON teams (name);
+            CREATE UNIQUE INDEX leagues_competition_season ON leagues (competition_id, season_id);
+            CREATE UNIQUE INDEX matches_league_date_teams ON matches (league_id, date, team1_id, team2_id);
+        ",
+    },
+    Migration {
+        version: 4,
+        description: "Indexes for per-team and per-league lookups, match_results and team_matches views",
+        sql: "
+            -- Matches of a league are covered by matches_league_date_teams
+            CREATE INDEX matches_team1_date ON matches (team1_id, date);
+            CREATE INDEX matches_team2_date ON matches (team2_id, date);
+            CREATE INDEX matches_date ON matches (date);
+            CREATE INDEX leagues_season ON leagues (season_id);
+            CREATE INDEX goals_match ON goals (match_id);
+            CREATE INDEX goals_player ON goals (player_id);
+            CREATE INDEX groups_league ON groups (league_id);
+            CREATE INDEX group_teams_team ON group_teams (team_id);
+            CREATE INDEX team_aliases_team ON team_aliases (team_id);
+
+            -- One row per match with the names of its teams and league. The outcome is taken from the score after
+            -- extra time, while the winner of a drawn match is the winner of the penalty shoot-out, if there was one.
+            CREATE VIEW match_results AS
+            SELECT
+                matches.id          AS match_id,
+                matches.date,
+                matches.kickoff,
+                competitions.code   AS competition,
+                seasons.name        AS season,
+                leagues.name        AS league,
+                matches.stage,
+                matches.round,
+                team1.name          AS team1,
+                team2.name          AS team2,
+                matches.team1_score,
+                matches.team2_score,
+                CASE
+                    WHEN matches.team1_score IS NULL OR matches.team2_score IS NULL THEN NULL
+                    WHEN matches.team1_score > matches.team2_score THEN 'team1'
+                    WHEN matches.team1_score < matches.team2_score THEN 'team2'
+                    ELSE 'draw'
+                END                 AS outcome,
+                CASE
+                    WHEN matches.team1_score > matches.team2_score THEN team1.name
+                    WHEN matches.team1_score < matches.team2_score THEN team2.name
+                    WHEN matches.team1_score_p > matches.team2_score_p THEN team1.name
+                    WHEN matches.team1_score_p < matches.team2_score_p THEN team2.name
+                END                 AS winner,
+                venues.name         AS venue
+            FROM matches
+            JOIN leagues ON leagues.id = matches.league_id
+            LEFT JOIN competitions ON competitions.id = leagues.competition_id
+            LEFT JOIN seasons ON seasons.id = leagues.season_id
+            JOIN teams AS team1 ON team1.id = matches.team1_id
+            JOIN teams AS team2 ON team2.id = matches.team2_id
+            LEFT JOIN venues ON venues.id = matches.venue_id;
+
+            -- Two rows per match, one from the point of view of each team, with the result as W, D or L
+            CREATE VIEW team_matches AS
+            SELECT
+                sides.match_id,
+                sides.date,
+                sides.league_id,
+                sides.team_id,
+                team.name           AS team,
+                sides.opponent_id,
+                opponent.name       AS opponent,
+                sides.is_team1,
+                sides.goals_for,
+                sides.goals_against,
+                CASE
+                    WHEN sides.goals_for IS NULL OR sides.goals_against IS NULL THEN NULL
+                    WHEN sides.goals_for > sides.goals_against THEN 'W'
+                    WHEN sides.goals_for < sides.goals_against THEN 'L'
+                    ELSE 'D'
+                END                 AS result
+            FROM (
+                SELECT id AS match_id, date, league_id, team1_id AS team_id, team2_id AS opponent_id, 1 AS is_team1,
+                    team1_score AS goals_for, team2_score AS goals_against
+                FROM matches
+                UNION ALL
+                SELECT id, date, league_id, team2_id, team1_id, 0, team2_score, team1_score
+                FROM matches
+            ) AS sides
+            JOIN teams AS team ON team.id = sides.team_id
+            JOIN teams AS opponent ON opponent.id = sides.opponent_id;
+        ",
+    },
+    Migration {
+        version: 5,
+        description: "League tables",
+        sql: "
+            -- One table per league, with group_id NULL, and one per group of the league
+            CREATE TABLE standings (
+                league_id       INTEGER NOT NULL REFERENCES leagues(id),
+                group_id        INTEGER REFERENCES groups(id),
+                team_id         INTEGER NOT NULL REFERENCES teams(id),
+                position        INTEGER NOT NULL,
+                played          INTEGER NOT NULL,
+                won             INTEGER NOT NULL,
+                drawn           INTEGER NOT NULL,
+                lost            INTEGER NOT NULL,
+                goals_for       INTEGER NOT NULL,
+                goals_against   INTEGER NOT NULL,
+                goal_difference INTEGER NOT NULL,
+                points          INTEGER NOT NULL
+            );
+
+            CREATE INDEX standings_league_group ON standings (league_id, group_id, position);
+            CREATE INDEX standings_team ON standings (team_id);
+        ",
+    },
+    Migration {
+        version: 6,
+        description: "League tables after every round",
+        sql: "
+            CREATE TABLE standings_history (
+                league_id       INTEGER NOT NULL REFERENCES leagues(id),
+                group_id        INTEGER REFERENCES groups(id),
+                matchday        INTEGER NOT NULL,
+                round           TEXT    NOT NULL,
+                team_id         INTEGER NOT NULL REFERENCES teams(id),
+                position        INTEGER NOT NULL,
+                played          INTEGER NOT NULL,
+                won             INTEGER NOT NULL,
+                drawn           INTEGER NOT NULL,
+                lost            INTEGER NOT NULL,
+                goals_for       INTEGER NOT NULL,
+                goals_against   INTEGER NOT NULL,
+                goal_difference INTEGER NOT NULL,
+                points          INTEGER NOT NULL
+            );
+
+            CREATE INDEX standings_history_league_group ON standings_history (league_id, group_id, matchday, position);
+            CREATE INDEX standings_history_team ON standings_history (team_id, league_id);
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "Elo ratings",
+        sql: "
+            CREATE TABLE team_ratings (
+                team_id         INTEGER NOT NULL REFERENCES teams(id),
+                match_id        INTEGER NOT NULL REFERENCES matches(id),
+                date            TEXT    NOT NULL,
+                rating_before   REAL    NOT NULL,
+                rating          REAL    NOT NULL,
+                PRIMARY KEY (team_id, match_id)
+            );
+
+            CREATE INDEX team_ratings_team_date ON team_ratings (team_id, date, match_id);
+            CREATE INDEX team_ratings_match ON team_ratings (match_id);
+        ",
+    },
+    Migration {
+        version: 8,
+        description: "Scotland uses the football.json code sco",
+        sql: "
+            UPDATE countries SET code = 'sco' WHERE code = 'sc';
+        ",
+    },
+];
+
+/// The schema version this version of the loader writes
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer version of the loader. Writing to it could lose data.
+    NewerSchema { found: i32, supported: i32 },
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NewerSchema { found, supported } => write!(
+                f,
+                "The database has schema version {}, but this version of football_json_loader only supports up to {}. Please upgrade football_json_loader",
+                found, supported
+            ),
+            MigrationError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+/// Returns the schema version of a database. Databases created before migrations were introduced have version 0.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.query_row("PRAGMA user_version", (), |row| row.get(0))
+}
+
+/// Brings the schema of a database up to date by applying every migration it is missing, each in its own
+/// transaction. Refuses databases with a schema newer than the latest migration.
+pub fn migrate(conn: &mut Connection) -> Result<(), MigrationError> {
+    let current_version = schema_version(conn)?;
+    if current_version > latest_version() {
+        return Err(MigrationError::NewerSchema { found: current_version, supported: latest_version() });
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current_version) {
+        info!("Migrating database to schema version {}: {}", migration.version, migration.description);
+
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schema written by the loader before migrations were introduced, as in the checked-in football_json.sqlite
+    const VERSION_0_SCHEMA: &str = "
+        CREATE TABLE teams (
+            id      INTEGER PRIMARY KEY,
+            name    TEXT    NOT NULL,
+            code    TEXT
+        );
+
+        CREATE TABLE leagues (
+            id      INTEGER PRIMARY KEY,
+            name    TEXT    NOT NULL
+        );
+
+        CREATE TABLE matches (
+            id          INTEGER PRIMARY KEY,
+            date        TEXT NOT NULL,
+            league_id   INTEGER NOT NULL REFERENCES leagues(id),
+            team1_id    INTEGER NOT NULL REFERENCES teams(id),
+            team2_id    INTEGER NOT NULL REFERENCES teams(id),
+            stage       TEXT,
+            round       TEXT,
+            team1_score INTEGER,
+            team2_score INTEGER
+        );
+    ";
+
+    fn version_0_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(VERSION_0_SCHEMA).unwrap();
+        conn.execute_batch("
+            INSERT INTO teams (id, name, code) VALUES (56, 'Arsenal FC', 'ARS'), (57, 'Fulham FC', 'FUL');
+            INSERT INTO leagues (id, name) VALUES (1, 'English Premier League 2020/21');
+            INSERT INTO matches (id, date, league_id, team1_id, team2_id, round, team1_score, team2_score)
+            VALUES (1, '2020-09-12', 1, 57, 56, 'Matchday 1', 0, 3);
+        ").unwrap();
+        conn
+    }
+
+    #[test]
+    fn upgrades_version_0_database() {
+        let mut conn = version_0_database();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+
+        // Existing rows keep their IDs and get the defaults of the new columns
+        let team: (i32, String, String) = conn.query_row(
+            "SELECT id, name, origin FROM teams WHERE name = 'Arsenal FC'", (), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        ).unwrap();
+        assert_eq!(team, (56, "Arsenal FC".to_string(), "declared".to_string()));
+
+        let match_count: i32 = conn.query_row("SELECT COUNT(*) FROM match_results", (), |row| row.get(0)).unwrap();
+        assert_eq!(match_count, 1);
+
+        // Names are unique from version 3 on
+        assert!(conn.execute("INSERT INTO teams (id, name) VALUES (1, 'Arsenal FC')", ()).is_err());
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let mut conn = version_0_database();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        match migrate(&mut conn) {
+            Err(MigrationError::NewerSchema { found, supported }) => {
+                assert_eq!(found, latest_version() + 1);
+                assert_eq!(supported, latest_version());
+            },
+            other => panic!("expected NewerSchema, got {:?}", other),
+        };
+    }
+}
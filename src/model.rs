@@ -1,6 +1,8 @@
-use chrono::NaiveDate;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Serialize, Deserialize};
 
+use crate::competition::Season;
+
 #[derive(Serialize, Deserialize)]
 pub struct MatchTeamList {
     pub name: String,
@@ -8,13 +10,84 @@ pub struct MatchTeamList {
     pub teams: Vec<Team>
 }
 
+/// Content of a *.groups.json file
+#[derive(Serialize, Deserialize)]
+pub struct GroupList {
+    /// ID the loader gave the league of the league file next to the groups file, if there is one
+    #[serde(skip_deserializing, default)]
+    pub league_id: Option<i32>,
+    #[serde(skip_deserializing, default)]
+    pub source_file: String,
+    pub name: String,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Group {
+    #[serde(skip_deserializing, default)]
+    pub id: i32,
+    pub name: String,
+    pub teams: Vec<GroupTeam>,
+}
+
+/// Group members are either listed by name or as team objects
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GroupTeam {
+    Name(String),
+    Team(Team),
+}
+
+impl GroupTeam {
+    pub fn get_name(&self) -> &str {
+        match self {
+            GroupTeam::Name(n) => n,
+            GroupTeam::Team(t) => &t.name,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Clone)]
 pub struct Team {
     #[serde(skip_deserializing, default)]
     pub id: i32,
     pub name: String,
-    pub code: Option<String>
+    pub code: Option<String>,
+    /// Country of the club as given in international clubs files, e.g. `England`
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(skip_deserializing, default)]
+    pub source_file: String,
+    #[serde(skip_deserializing, default)]
+    pub origin: TeamOrigin,
+}
+
+/// Where a team comes from
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TeamOrigin {
+    /// Listed in a clubs file
+    #[default]
+    Declared,
+    /// Only found as a participant of a match or group
+    Inferred,
+}
+
+impl TeamOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamOrigin::Declared => "declared",
+            TeamOrigin::Inferred => "inferred",
+        }
+    }
+    
+    pub fn from_name(origin: &str) -> Option<TeamOrigin> {
+        match origin {
+            "declared" => Some(TeamOrigin::Declared),
+            "inferred" => Some(TeamOrigin::Inferred),
+            _ => None,
+        }
+    }
 }
 
 
@@ -46,20 +119,96 @@ impl LeagueJsonFormat {
             LeagueJsonFormat::NewFormat(n) => n.id = id,
         }
     }
+    
+    /// Returns all matches of the league
+    pub fn get_matches(&self) -> Vec<&OldLeagueMatch> {
+        match &self {
+            LeagueJsonFormat::OldFormat(o) => o.rounds.iter()
+                .flat_map(|r| r.matches.iter())
+                .collect(),
+            LeagueJsonFormat::NewFormat(n) => n.matches.iter()
+                .map(|m| &m.match_info)
+                .collect(),
+        }
+    }
+    
+    /// Path of the file this league was loaded from, relative to the football.json root
+    pub fn get_source_file(&self) -> &str {
+        match &self {
+            LeagueJsonFormat::OldFormat(o) => &o.source_file,
+            LeagueJsonFormat::NewFormat(n) => &n.source_file,
+        }
+    }
+    
+    pub fn set_source_file(&mut self, source_file: String) {
+        match self {
+            LeagueJsonFormat::OldFormat(o) => o.source_file = source_file,
+            LeagueJsonFormat::NewFormat(n) => n.source_file = source_file,
+        }
+    }
+    
+    /// Code of the competition this league is an edition of, e.g. `en.1`
+    pub fn get_competition_code(&self) -> Option<&str> {
+        match &self {
+            LeagueJsonFormat::OldFormat(o) => o.competition_code.as_deref(),
+            LeagueJsonFormat::NewFormat(n) => n.competition_code.as_deref(),
+        }
+    }
+    
+    pub fn set_competition_code(&mut self, code: Option<String>) {
+        match self {
+            LeagueJsonFormat::OldFormat(o) => o.competition_code = code,
+            LeagueJsonFormat::NewFormat(n) => n.competition_code = code,
+        }
+    }
+    
+    pub fn get_season(&self) -> Option<&Season> {
+        match &self {
+            LeagueJsonFormat::OldFormat(o) => o.season.as_ref(),
+            LeagueJsonFormat::NewFormat(n) => n.season.as_ref(),
+        }
+    }
+    
+    pub fn set_season(&mut self, season: Option<Season>) {
+        match self {
+            LeagueJsonFormat::OldFormat(o) => o.season = season,
+            LeagueJsonFormat::NewFormat(n) => n.season = season,
+        }
+    }
 }
 
 pub trait LeagueMatch {
     fn get_date(&self) -> &NaiveDate;
     fn get_team_1_name(&self) -> &str;
     fn get_team_2_name(&self) -> &str;
-    fn get_team_1_score(&self) -> Option<i32>;
-    fn get_team_2_score(&self) -> Option<i32>;
+    fn get_score(&self) -> Option<&Score>;
+    /// Local date and time of the kick-off
+    fn get_kickoff(&self) -> Option<NaiveDateTime>;
+    /// Offset from UTC of the kick-off time, if it was given
+    fn get_utc_offset(&self) -> Option<FixedOffset>;
+    fn get_ground(&self) -> Option<&str>;
+
+    /// Goals scored by team 1, after extra time if it was played
+    fn get_team_1_score(&self) -> Option<i32> {
+        self.get_score()?.get_result().map(|s| s.0)
+    }
+
+    /// Goals scored by team 2, after extra time if it was played
+    fn get_team_2_score(&self) -> Option<i32> {
+        self.get_score()?.get_result().map(|s| s.1)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct OldLeagueFormat {
     #[serde(skip_deserializing, default)]
     pub id: i32,
+    #[serde(skip_deserializing, default)]
+    pub source_file: String,
+    #[serde(skip_deserializing, default)]
+    pub competition_code: Option<String>,
+    #[serde(skip_deserializing, default)]
+    pub season: Option<Season>,
     pub name: String,
     pub rounds: Vec<LeagueRound>,
 }
@@ -73,9 +222,19 @@ pub struct LeagueRound {
 #[derive(Serialize, Deserialize)]
 pub struct OldLeagueMatch {
     pub date: NaiveDate,
+    /// Local kick-off time, e.g. `20:45` or `18:00 UTC+3`
+    pub time: Option<String>,
+    /// Venue, e.g. `Luzhniki Stadium, Moscow`
+    pub ground: Option<String>,
     pub team1: String,
     pub team2: String,
-    pub score: Option<LeagueScoreFormat>,
+    pub score: Option<Score>,
+    /// Goals credited to team 1
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goals1: Vec<Goal>,
+    /// Goals credited to team 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goals2: Vec<Goal>,
 }
 
 impl LeagueMatch for OldLeagueMatch {
@@ -91,76 +250,144 @@ impl LeagueMatch for OldLeagueMatch {
         &self.team2
     }
 
-    fn get_team_1_score(&self) -> Option<i32> {
-        if let Some(s) = &self.score {
-            return Some(s.get_team_1_score());
-        }
-        
-        None
+    fn get_score(&self) -> Option<&Score> {
+        self.score.as_ref()
     }
 
-    fn get_team_2_score(&self) -> Option<i32> {
-        if let Some(s) = &self.score {
-            return Some(s.get_team_2_score());
-        }
-        
-        None
+    fn get_kickoff(&self) -> Option<NaiveDateTime> {
+        let (time, _) = parse_kickoff_time(self.time.as_deref()?)?;
+        Some(self.date.and_time(time))
     }
-}
 
-#[derive(Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum LeagueScoreFormat {
-    Normal(NormalLeagueScore),
-    Australian(AustralianScore),
-}
+    fn get_utc_offset(&self) -> Option<FixedOffset> {
+        parse_kickoff_time(self.time.as_deref()?)?.1
+    }
 
-pub trait LeagueScore {
-    fn get_team_1_score(&self) -> i32;
-    fn get_team_2_score(&self) -> i32;
+    fn get_ground(&self) -> Option<&str> {
+        self.ground.as_deref()
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct NormalLeagueScore {
-    pub ft: Vec<i32>,
+/// Parses kick-off times like `20:45`, `18:00 UTC+3` or `19:30 UTC-04:00`
+fn parse_kickoff_time(time: &str) -> Option<(NaiveTime, Option<FixedOffset>)> {
+    let mut parts = time.split_whitespace();
+    let local_time = NaiveTime::parse_from_str(parts.next()?, "%H:%M").ok()?;
+    let utc_offset = parts.next().and_then(parse_utc_offset);
+
+    Some((local_time, utc_offset))
 }
 
-impl LeagueScore for NormalLeagueScore {
-    fn get_team_1_score(&self) -> i32 {
-        self.ft[0]
-    }
-    
-    fn get_team_2_score(&self) -> i32 {
-        self.ft[1]
+/// Parses offsets like `UTC`, `UTC+3` or `UTC-04:00`
+fn parse_utc_offset(offset: &str) -> Option<FixedOffset> {
+    let offset = offset.strip_prefix("UTC")?;
+    if offset.is_empty() {
+        return FixedOffset::east_opt(0);
     }
+
+    let (sign, hours_minutes) = match offset.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, offset.strip_prefix('+')?),
+    };
+
+    let mut parts = hours_minutes.splitn(2, ':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = parts.next().unwrap_or("0").parse().ok()?;
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct AustralianScore {
-    pub et: Vec<i32>,
+/// A goal. Own goals are credited to the team that benefits from them,
+/// so the scorer plays for the other team.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Goal {
+    /// Name of the scorer
+    pub name: String,
+    pub minute: i32,
+    /// Minutes into stoppage time, e.g. 3 for 90+3
+    pub offset: Option<i32>,
+    #[serde(default)]
+    pub penalty: bool,
+    #[serde(default)]
+    pub owngoal: bool,
 }
 
-impl LeagueScore for AustralianScore {
-    fn get_team_1_score(&self) -> i32 {
-        self.et[0]
-    }
-    
-    fn get_team_2_score(&self) -> i32 {
-        self.et[1]
+/// Goals scored by team 1 and team 2
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ScorePair(pub i32, pub i32);
+
+/// Score of a match in every phase that was played. Phases are cumulative,
+/// e.g. `et` includes the goals scored in regular time.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(from = "RawScore")]
+pub struct Score {
+    /// Half-time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ht: Option<ScorePair>,
+    /// Full-time, after 90 minutes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ft: Option<ScorePair>,
+    /// After extra time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub et: Option<ScorePair>,
+    /// Penalty shoot-out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<ScorePair>,
+    /// Aggregate over both legs of a tie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agg: Option<ScorePair>,
+    /// Phases that didn't hold exactly two scores, e.g. `ft: [3]`. They are left out of the score and reported.
+    #[serde(skip)]
+    pub invalid_phases: Vec<String>,
+}
+
+impl Score {
+    /// The result of the match without the penalty shoot-out: after extra time if it was played, full-time otherwise
+    pub fn get_result(&self) -> Option<ScorePair> {
+        self.et.or(self.ft)
     }
 }
 
-impl LeagueScore for LeagueScoreFormat {
-    fn get_team_1_score(&self) -> i32 {
-        match self {
-            LeagueScoreFormat::Normal(n) => n.get_team_1_score(),
-            LeagueScoreFormat::Australian(a) => a.get_team_2_score(),
+/// A score as written in the file, before the phases are checked
+#[derive(Deserialize)]
+struct RawScore {
+    #[serde(default)]
+    ht: Option<Vec<Option<i32>>>,
+    #[serde(default)]
+    ft: Option<Vec<Option<i32>>>,
+    #[serde(default)]
+    et: Option<Vec<Option<i32>>>,
+    #[serde(default)]
+    p: Option<Vec<Option<i32>>>,
+    #[serde(default)]
+    agg: Option<Vec<Option<i32>>>,
+}
+
+impl From<RawScore> for Score {
+    fn from(raw: RawScore) -> Self {
+        let mut invalid_phases = Vec::new();
+
+        Score {
+            ht: score_pair("ht", raw.ht, &mut invalid_phases),
+            ft: score_pair("ft", raw.ft, &mut invalid_phases),
+            et: score_pair("et", raw.et, &mut invalid_phases),
+            p: score_pair("p", raw.p, &mut invalid_phases),
+            agg: score_pair("agg", raw.agg, &mut invalid_phases),
+            invalid_phases,
         }
     }
-    fn get_team_2_score(&self) -> i32 {
-        match self {
-            LeagueScoreFormat::Normal(n) => n.get_team_2_score(),
-            LeagueScoreFormat::Australian(a) => a.get_team_2_score(),
+}
+
+/// Reads a score phase such as `"ft": [2, 1]`. Phases that don't hold exactly two scores are added to `invalid_phases`.
+fn score_pair(phase: &str, scores: Option<Vec<Option<i32>>>, invalid_phases: &mut Vec<String>) -> Option<ScorePair> {
+    match scores.as_deref() {
+        None => None,
+        Some([Some(team1), Some(team2)]) => Some(ScorePair(*team1, *team2)),
+        Some(other) => {
+            let scores: Vec<String> = other.iter()
+                .map(|s| s.map(|g| g.to_string()).unwrap_or_else(|| "null".to_string()))
+                .collect();
+            invalid_phases.push(format!("{}: [{}]", phase, scores.join(", ")));
+            None
         }
     }
 }
@@ -169,6 +396,12 @@ impl LeagueScore for LeagueScoreFormat {
 pub struct NewLeagueFormat {
     #[serde(skip_deserializing, default)]
     pub id: i32,
+    #[serde(skip_deserializing, default)]
+    pub source_file: String,
+    #[serde(skip_deserializing, default)]
+    pub competition_code: Option<String>,
+    #[serde(skip_deserializing, default)]
+    pub season: Option<Season>,
     pub name: String,
     pub matches: Vec<NewLeagueMatch>
 }
@@ -194,11 +427,77 @@ impl LeagueMatch for NewLeagueMatch {
         self.match_info.get_team_2_name()
     }
 
-    fn get_team_1_score(&self) -> Option<i32> {
-        self.match_info.get_team_1_score()
+    fn get_score(&self) -> Option<&Score> {
+        self.match_info.get_score()
     }
 
-    fn get_team_2_score(&self) -> Option<i32> {
-        self.match_info.get_team_2_score()
+    fn get_kickoff(&self) -> Option<NaiveDateTime> {
+        self.match_info.get_kickoff()
+    }
+
+    fn get_utc_offset(&self) -> Option<FixedOffset> {
+        self.match_info.get_utc_offset()
     }
-}
\ No newline at end of file
+
+    fn get_ground(&self) -> Option<&str> {
+        self.match_info.get_ground()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_keeps_valid_phases_and_lists_invalid_ones() {
+        let score: Score = serde_json::from_str(r#"{"ht": [1, 0], "ft": [3], "et": [null, 2]}"#).unwrap();
+
+        assert_eq!(score.ht, Some(ScorePair(1, 0)));
+        assert_eq!(score.ft, None);
+        assert_eq!(score.et, None);
+        assert_eq!(score.invalid_phases, vec!["ft: [3]".to_string(), "et: [null, 2]".to_string()]);
+    }
+
+    fn hours(h: i32) -> Option<FixedOffset> {
+        FixedOffset::east_opt(h * 3600)
+    }
+
+    #[test]
+    fn parses_utc_offsets() {
+        assert_eq!(parse_utc_offset("UTC"), hours(0));
+        assert_eq!(parse_utc_offset("UTC+3"), hours(3));
+        assert_eq!(parse_utc_offset("UTC-4"), hours(-4));
+        assert_eq!(parse_utc_offset("UTC-04:00"), hours(-4));
+        assert_eq!(parse_utc_offset("UTC+05:30"), FixedOffset::east_opt(5 * 3600 + 30 * 60));
+    }
+
+    #[test]
+    fn rejects_malformed_utc_offsets() {
+        assert_eq!(parse_utc_offset("GMT+1"), None);
+        assert_eq!(parse_utc_offset("UTC3"), None);
+        assert_eq!(parse_utc_offset("UTC+"), None);
+        assert_eq!(parse_utc_offset("UTC+x"), None);
+        assert_eq!(parse_utc_offset("UTC+3:xx"), None);
+        assert_eq!(parse_utc_offset("UTC+25"), None);
+    }
+
+    #[test]
+    fn parses_kickoff_times_with_and_without_offset() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        assert_eq!(parse_kickoff_time("20:45"), Some((time(20, 45), None)));
+        assert_eq!(parse_kickoff_time("18:00 UTC+3"), Some((time(18, 0), hours(3))));
+        assert_eq!(parse_kickoff_time("19:30 UTC-04:00"), Some((time(19, 30), hours(-4))));
+        assert_eq!(parse_kickoff_time("15:00 UTC"), Some((time(15, 0), hours(0))));
+
+        // A malformed offset leaves the local time
+        assert_eq!(parse_kickoff_time("20:45 CET"), Some((time(20, 45), None)));
+    }
+
+    #[test]
+    fn rejects_malformed_kickoff_times() {
+        assert_eq!(parse_kickoff_time(""), None);
+        assert_eq!(parse_kickoff_time("25:00"), None);
+        assert_eq!(parse_kickoff_time("20.45"), None);
+        assert_eq!(parse_kickoff_time("evening UTC+1"), None);
+    }
+}
@@ -0,0 +1,372 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use chrono::NaiveDate;
+
+/// How the size of a win scales the rating change
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MarginOfVictory {
+    /// Every win counts the same
+    Off,
+    /// As used by the World Football Elo Ratings: 1.5 times the change for a two goal win,
+    /// (11 + goals) / 8 times for larger wins
+    #[default]
+    WorldFootball,
+    /// The logarithm of the goal difference, damped when the favourite wins, so that strong teams
+    /// running up the score don't inflate their rating
+    Logarithmic,
+}
+
+impl MarginOfVictory {
+    /// Short name, as used on the command line and in the metadata table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarginOfVictory::Off => "off",
+            MarginOfVictory::WorldFootball => "world-football",
+            MarginOfVictory::Logarithmic => "log",
+        }
+    }
+
+    /// Multiplier of the rating change for a win by `goal_difference` goals, where the winner was
+    /// rated `rating_difference` points above the loser
+    fn multiplier(&self, goal_difference: i32, rating_difference: f64) -> f64 {
+        let goal_difference = goal_difference.abs();
+        if goal_difference == 0 {
+            return 1.0;
+        }
+
+        match self {
+            MarginOfVictory::Off => 1.0,
+            MarginOfVictory::WorldFootball => match goal_difference {
+                1 => 1.0,
+                2 => 1.5,
+                n => (11.0 + n as f64) / 8.0,
+            },
+            MarginOfVictory::Logarithmic => (goal_difference as f64 + 1.0).ln() * 2.2 / (rating_difference * 0.001 + 2.2),
+        }
+    }
+}
+
+impl FromStr for MarginOfVictory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(MarginOfVictory::Off),
+            "world-football" => Ok(MarginOfVictory::WorldFootball),
+            "log" => Ok(MarginOfVictory::Logarithmic),
+            _ => Err(format!("Unknown margin of victory multiplier: {} (expected off, world-football or log)", s)),
+        }
+    }
+}
+
+impl fmt::Display for MarginOfVictory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Settings of the Elo ratings
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EloSettings {
+    /// Rating of a team before its first match, and the mean ratings regress to
+    pub initial_rating: f64,
+    /// Largest change of rating from a single match, before the margin of victory multiplier
+    pub k_factor: f64,
+    /// Rating points added to team 1, the home team, when working out the expected result
+    pub home_advantage: f64,
+    pub margin_of_victory: MarginOfVictory,
+    /// Share of the distance to the initial rating a team's rating moves back by at the start of a new season,
+    /// from 0 (not at all) to 1 (every season starts from scratch)
+    pub season_regression: f64,
+}
+
+impl Default for EloSettings {
+    fn default() -> Self {
+        EloSettings {
+            initial_rating: 1500.0,
+            k_factor: 20.0,
+            home_advantage: 100.0,
+            margin_of_victory: MarginOfVictory::default(),
+            season_regression: 0.0,
+        }
+    }
+}
+
+impl EloSettings {
+    /// Checks that the settings make sense, e.g. that the regression is between 0 and 1
+    pub fn validate(&self) -> Result<(), String> {
+        if self.k_factor <= 0.0 {
+            return Err(format!("Invalid K-factor: {} (expected more than 0)", self.k_factor));
+        }
+
+        if !(0.0..=1.0).contains(&self.season_regression) {
+            return Err(format!("Invalid season regression: {} (expected 0 to 1)", self.season_regression));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for EloSettings {
+    /// Lists the settings, e.g. `initial=1500 k=20 home=100 margin=world-football regression=0`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "initial={} k={} home={} margin={} regression={}",
+            self.initial_rating, self.k_factor, self.home_advantage, self.margin_of_victory, self.season_regression
+        )
+    }
+}
+
+/// A played match, as needed to rate the teams
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RatedMatch {
+    pub match_id: i64,
+    pub date: NaiveDate,
+    /// Start year of the season the match belongs to, if it is known
+    pub season_start_year: Option<i32>,
+    pub team1_id: i32,
+    pub team2_id: i32,
+    pub team1_score: i32,
+    pub team2_score: i32,
+}
+
+/// The rating of a team before and after one of its matches
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RatingEntry {
+    pub team_id: i32,
+    pub match_id: i64,
+    pub date: NaiveDate,
+    /// Rating going into the match, after any regression at the start of the season
+    pub rating_before: f64,
+    pub rating: f64,
+}
+
+/// Elo ratings of every team, updated one match at a time. Matches have to be given in the order they were played.
+pub struct EloRatings {
+    settings: EloSettings,
+    /// Current rating of every team that played
+    ratings: HashMap<i32, f64>,
+    /// Latest season every team played in, to tell when a new one starts
+    seasons: HashMap<i32, i32>,
+    /// Every rating change so far, in the order the matches were rated
+    history: Vec<RatingEntry>,
+}
+
+impl EloRatings {
+    pub fn new(settings: EloSettings) -> Self {
+        EloRatings { settings, ratings: HashMap::new(), seasons: HashMap::new(), history: Vec::new() }
+    }
+
+    /// Current rating of a team. Teams that haven't played yet have the initial rating.
+    pub fn rating(&self, team_id: i32) -> f64 {
+        self.ratings.get(&team_id).copied().unwrap_or(self.settings.initial_rating)
+    }
+
+    /// Rating of a team at the end of a date, if it had played by then
+    pub fn rating_at(&self, team_id: i32, date: NaiveDate) -> Option<f64> {
+        self.history.iter()
+            .rev()
+            .find(|e| e.team_id == team_id && e.date <= date)
+            .map(|e| e.rating)
+    }
+
+    /// Every rating change so far, in the order the matches were rated
+    pub fn history(&self) -> &[RatingEntry] {
+        &self.history
+    }
+
+    /// Updates the ratings of both teams of a match, and returns their entries
+    pub fn rate(&mut self, m: &RatedMatch) -> (RatingEntry, RatingEntry) {
+        let rating1 = self.rating_for_season(m.team1_id, m.season_start_year);
+        let rating2 = self.rating_for_season(m.team2_id, m.season_start_year);
+
+        let difference = rating1 + self.settings.home_advantage - rating2;
+        let expected1 = 1.0 / (1.0 + 10f64.powf(-difference / 400.0));
+        let actual1 = match m.team1_score.cmp(&m.team2_score) {
+            std::cmp::Ordering::Greater => 1.0,
+            std::cmp::Ordering::Equal => 0.5,
+            std::cmp::Ordering::Less => 0.0,
+        };
+
+        // The margin of victory is damped by how much the winner was favoured
+        let winner_difference = if actual1 < 0.5 { -difference } else { difference };
+        let multiplier = self.settings.margin_of_victory.multiplier(m.team1_score - m.team2_score, winner_difference);
+        let change = self.settings.k_factor * multiplier * (actual1 - expected1);
+
+        let entry1 = RatingEntry { team_id: m.team1_id, match_id: m.match_id, date: m.date, rating_before: rating1, rating: rating1 + change };
+        let entry2 = RatingEntry { team_id: m.team2_id, match_id: m.match_id, date: m.date, rating_before: rating2, rating: rating2 - change };
+
+        for entry in [entry1, entry2] {
+            self.ratings.insert(entry.team_id, entry.rating);
+            self.history.push(entry);
+        }
+
+        (entry1, entry2)
+    }
+
+    /// Rating of a team going into a match of the given season. The first match of a new season
+    /// moves the rating back towards the initial rating.
+    fn rating_for_season(&mut self, team_id: i32, season_start_year: Option<i32>) -> f64 {
+        let rating = self.rating(team_id);
+        let season = match season_start_year {
+            Some(s) => s,
+            None => return rating,
+        };
+
+        // Seasons can overlap, e.g. a cup final of last season after the first league match of this one
+        match self.seasons.get(&team_id).copied() {
+            Some(latest) if latest >= season => rating,
+            latest => {
+                self.seasons.insert(team_id, season);
+                if latest.is_none() {
+                    return rating;
+                }
+
+                rating + (self.settings.initial_rating - rating) * self.settings.season_regression
+            },
+        }
+    }
+}
+
+/// Rates all matches in the order they were played, by date and then by ID
+pub fn rate_matches(matches: &[RatedMatch], settings: EloSettings) -> EloRatings {
+    let mut ordered: Vec<&RatedMatch> = matches.iter().collect();
+    ordered.sort_by_key(|m| (m.date, m.match_id));
+
+    let mut ratings = EloRatings::new(settings);
+    for m in ordered {
+        ratings.rate(m);
+    }
+
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rated_match(match_id: i64, date: &str, season_start_year: Option<i32>, team1_id: i32, team2_id: i32, team1_score: i32, team2_score: i32) -> RatedMatch {
+        RatedMatch {
+            match_id,
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            season_start_year,
+            team1_id,
+            team2_id,
+            team1_score,
+            team2_score,
+        }
+    }
+
+    /// Settings without home advantage or margin of victory, so a win between even teams is worth k / 2
+    fn plain_settings() -> EloSettings {
+        EloSettings { home_advantage: 0.0, margin_of_victory: MarginOfVictory::Off, ..EloSettings::default() }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn win_between_even_teams_moves_half_the_k_factor() {
+        let mut ratings = EloRatings::new(plain_settings());
+        let (entry1, entry2) = ratings.rate(&rated_match(1, "2020-09-12", None, 1, 2, 1, 0));
+
+        assert_close(entry1.rating_before, 1500.0);
+        assert_close(entry1.rating, 1510.0);
+        assert_close(entry2.rating, 1490.0);
+        assert_close(ratings.rating(1), 1510.0);
+        assert_close(ratings.rating(3), 1500.0);
+    }
+
+    #[test]
+    fn home_advantage_costs_the_home_team_points_for_a_draw() {
+        let settings = EloSettings { margin_of_victory: MarginOfVictory::Off, ..EloSettings::default() };
+        let mut ratings = EloRatings::new(settings);
+        let (entry1, entry2) = ratings.rate(&rated_match(1, "2020-09-12", None, 1, 2, 1, 1));
+
+        // The home team was expected to score 1 / (1 + 10^(-100 / 400)) = 0.640
+        let change = 20.0 * (0.5 - 1.0 / (1.0 + 10f64.powf(-0.25)));
+        assert_close(entry1.rating, 1500.0 + change);
+        assert_close(entry2.rating, 1500.0 - change);
+        assert!(entry1.rating < 1500.0);
+    }
+
+    #[test]
+    fn margin_of_victory_scales_the_change() {
+        let settings = EloSettings { margin_of_victory: MarginOfVictory::WorldFootball, ..plain_settings() };
+        let mut ratings = EloRatings::new(settings);
+
+        // 1.75 times the change for a three goal win
+        let (entry1, _) = ratings.rate(&rated_match(1, "2020-09-12", None, 1, 2, 3, 0));
+        assert_close(entry1.rating, 1517.5);
+
+        assert_close(MarginOfVictory::WorldFootball.multiplier(2, 0.0), 1.5);
+        assert_close(MarginOfVictory::WorldFootball.multiplier(-1, 0.0), 1.0);
+        assert_close(MarginOfVictory::Off.multiplier(5, 0.0), 1.0);
+
+        // A favourite running up the score gains less than an underdog winning by as much
+        let favourite = MarginOfVictory::Logarithmic.multiplier(4, 400.0);
+        let underdog = MarginOfVictory::Logarithmic.multiplier(4, -400.0);
+        assert!(favourite < underdog);
+    }
+
+    #[test]
+    fn ratings_regress_at_the_start_of_a_new_season() {
+        let settings = EloSettings { season_regression: 0.5, ..plain_settings() };
+        let ratings = rate_matches(&[
+            rated_match(1, "2020-09-12", Some(2020), 1, 2, 1, 0),
+            rated_match(2, "2021-08-14", Some(2021), 1, 2, 0, 0),
+            // A cup final of last season, played after the new season started
+            rated_match(3, "2021-08-21", Some(2020), 2, 1, 0, 0),
+        ], settings);
+
+        let history = ratings.history();
+        assert_close(history[2].rating_before, 1505.0);
+        assert_close(history[3].rating_before, 1495.0);
+
+        // The old season doesn't start a new one again
+        assert_close(history[4].rating_before, history[3].rating);
+        assert_close(history[5].rating_before, history[2].rating);
+    }
+
+    #[test]
+    fn first_season_of_a_team_is_not_regressed() {
+        let settings = EloSettings { season_regression: 1.0, ..plain_settings() };
+        let ratings = rate_matches(&[
+            rated_match(1, "2020-09-12", Some(2020), 1, 2, 1, 0),
+            rated_match(2, "2021-08-14", Some(2021), 1, 3, 1, 0),
+        ], settings);
+
+        // 1 starts from scratch, while 3 plays its first match
+        assert_close(ratings.history()[2].rating_before, 1500.0);
+        assert_close(ratings.history()[3].rating_before, 1500.0);
+    }
+
+    #[test]
+    fn matches_are_rated_in_the_order_they_were_played() {
+        let ratings = rate_matches(&[
+            rated_match(2, "2020-09-19", None, 1, 2, 0, 1),
+            rated_match(1, "2020-09-12", None, 1, 2, 1, 0),
+        ], plain_settings());
+
+        let match_ids: Vec<i64> = ratings.history().iter().map(|e| e.match_id).collect();
+        assert_eq!(match_ids, vec![1, 1, 2, 2]);
+
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        assert_eq!(ratings.rating_at(1, date("2020-09-11")), None);
+        assert_close(ratings.rating_at(1, date("2020-09-15")).unwrap(), 1510.0);
+        assert_close(ratings.rating_at(1, date("2020-09-19")).unwrap(), ratings.rating(1));
+    }
+
+    #[test]
+    fn settings_are_validated_and_listed() {
+        assert!(EloSettings::default().validate().is_ok());
+        assert!(EloSettings { k_factor: 0.0, ..EloSettings::default() }.validate().is_err());
+        assert!(EloSettings { season_regression: 1.5, ..EloSettings::default() }.validate().is_err());
+
+        assert_eq!(EloSettings::default().to_string(), "initial=1500 k=20 home=100 margin=world-football regression=0");
+        assert_eq!("log".parse::<MarginOfVictory>(), Ok(MarginOfVictory::Logarithmic));
+        assert!("linear".parse::<MarginOfVictory>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::{fmt, str::FromStr, collections::BTreeMap};
+
+use log::warn;
+use serde::Serialize;
+
+/// Why a file, match or other record was skipped during a load
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IngestError {
+    /// The file could not be read
+    UnreadableFile { message: String },
+    /// The file is not valid JSON or does not match the format of its kind
+    InvalidJson { message: String },
+    /// The file could not be classified as clubs, league or groups file
+    UnknownFileKind,
+    /// A groups file whose league was not loaded
+    MissingLeague,
+    /// A match or group refers to a team that is not known
+    UnknownTeam { name: String },
+    /// A phase of the score of a match doesn't hold two scores. The match is loaded without it.
+    InvalidScore { message: String },
+    /// The database rejected a row
+    InsertFailed { table: String, message: String },
+}
+
+/// Broad kind of problem behind an IngestError
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IngestErrorCategory {
+    /// The data refers to something that doesn't exist, e.g. an unknown team
+    DataQuality,
+    /// A file could not be read or understood
+    Parse,
+    /// The database rejected the data
+    Database,
+}
+
+impl IngestError {
+    pub fn category(&self) -> IngestErrorCategory {
+        match self {
+            IngestError::UnreadableFile { .. } | IngestError::InvalidJson { .. } | IngestError::UnknownFileKind
+                | IngestError::InvalidScore { .. } => IngestErrorCategory::Parse,
+            IngestError::MissingLeague | IngestError::UnknownTeam { .. } => IngestErrorCategory::DataQuality,
+            IngestError::InsertFailed { .. } => IngestErrorCategory::Database,
+        }
+    }
+
+    /// Short name of the kind of error, as used in the JSON report
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IngestError::UnreadableFile { .. } => "unreadable_file",
+            IngestError::InvalidJson { .. } => "invalid_json",
+            IngestError::UnknownFileKind => "unknown_file_kind",
+            IngestError::MissingLeague => "missing_league",
+            IngestError::UnknownTeam { .. } => "unknown_team",
+            IngestError::InvalidScore { .. } => "invalid_score",
+            IngestError::InsertFailed { .. } => "insert_failed",
+        }
+    }
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestError::UnreadableFile { message } => write!(f, "Failed to read file: {}", message),
+            IngestError::InvalidJson { message } => write!(f, "Failed to deserialize: {}", message),
+            IngestError::UnknownFileKind => write!(f, "Unrecognized file"),
+            IngestError::MissingLeague => write!(f, "No league was loaded for these groups"),
+            IngestError::UnknownTeam { name } => write!(f, "Unknown team: {}", name),
+            IngestError::InvalidScore { message } => write!(f, "Invalid score: {}", message),
+            IngestError::InsertFailed { table, message } => write!(f, "Failed to insert into {}: {}", table, message),
+        }
+    }
+}
+
+/// A single skipped file or record
+#[derive(Serialize, Clone, Debug)]
+pub struct IngestEntry {
+    /// File the entry belongs to, relative to the football.json root
+    pub file: Option<String>,
+    /// What was skipped within the file, e.g. a match. None if the whole file was skipped.
+    pub item: Option<String>,
+    pub error: IngestError,
+}
+
+/// What writing a row did to the database
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpsertOutcome {
+    Inserted,
+    /// The row already existed and at least one of its values changed
+    Updated,
+    /// The row already existed with the same values
+    Unchanged,
+}
+
+/// Number of rows of a table that were inserted, updated or left unchanged during a load
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RowCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl RowCounts {
+    pub fn count(&mut self, outcome: UpsertOutcome) {
+        match outcome {
+            UpsertOutcome::Inserted => self.inserted += 1,
+            UpsertOutcome::Updated => self.updated += 1,
+            UpsertOutcome::Unchanged => self.unchanged += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: RowCounts) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+}
+
+impl fmt::Display for RowCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inserted, {} updated, {} unchanged", self.inserted, self.updated, self.unchanged)
+    }
+}
+
+/// Summary of a load: how much was written and everything that was skipped along the way
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct IngestReport {
+    pub files_loaded: usize,
+    pub teams: RowCounts,
+    pub leagues: RowCounts,
+    pub matches: RowCounts,
+    pub groups: RowCounts,
+    pub entries: Vec<IngestEntry>,
+}
+
+impl IngestReport {
+    pub fn new() -> Self {
+        IngestReport::default()
+    }
+
+    /// Records something that was skipped. The entry is logged as a warning as well.
+    pub fn record(&mut self, file: Option<String>, item: Option<String>, error: IngestError) {
+        match (&file, &item) {
+            (Some(file), Some(item)) => warn!("Skipping {} in {}: {}", item, file, error),
+            (Some(file), None) => warn!("Skipping {}: {}", file, error),
+            (None, Some(item)) => warn!("Skipping {}: {}", item, error),
+            (None, None) => warn!("{}", error),
+        };
+
+        self.entries.push(IngestEntry { file, item, error });
+    }
+
+    /// Adds the counts and entries of another report, e.g. the one of the database insert to the one of the JsonLoader
+    pub fn merge(&mut self, other: IngestReport) {
+        self.files_loaded += other.files_loaded;
+        self.teams.merge(other.teams);
+        self.leagues.merge(other.leagues);
+        self.matches.merge(other.matches);
+        self.groups.merge(other.groups);
+        self.entries.extend(other.entries);
+    }
+
+    /// Number of skipped entries per kind of error
+    pub fn skip_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.entries.iter() {
+            *counts.entry(entry.error.kind()).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// The most severe category among the skipped entries, if anything was skipped.
+    /// Database problems are the most severe, followed by parse and data quality problems.
+    pub fn worst_category(&self) -> Option<IngestErrorCategory> {
+        self.entries.iter().map(|e| e.error.category()).max()
+    }
+
+    /// Whether nothing was skipped
+    pub fn is_clean(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Renders the report as JSON, including the skip counts per kind of error
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("skipped".to_string(), serde_json::to_value(self.skip_counts())?);
+        }
+
+        serde_json::to_string_pretty(&value)
+    }
+}
+
+impl fmt::Display for IngestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Files loaded:     {}", self.files_loaded)?;
+        writeln!(f, "Teams:            {}", self.teams)?;
+        writeln!(f, "Leagues:          {}", self.leagues)?;
+        writeln!(f, "Matches:          {}", self.matches)?;
+        writeln!(f, "Groups:           {}", self.groups)?;
+        writeln!(f, "Skipped:          {}", self.entries.len())?;
+
+        for (kind, count) in self.skip_counts() {
+            writeln!(f, "  {:<18} {}", kind, count)?;
+        }
+
+        for entry in self.entries.iter() {
+            let location = match (&entry.file, &entry.item) {
+                (Some(file), Some(item)) => format!("{}: {}", file, item),
+                (Some(file), None) => file.clone(),
+                (None, Some(item)) => item.clone(),
+                (None, None) => String::new(),
+            };
+            writeln!(f, "  - {} ({})", location, entry.error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How the ingest report is printed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReportFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ReportFormat::Human),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("Unknown report format: {} (expected human or json)", s)),
+        }
+    }
+}
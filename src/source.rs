@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+
+use git2::{build::CheckoutBuilder, Delta, Error, Oid, Repository};
+use log::{info, warn};
+
+use crate::classify::companion_file;
+
+pub const DEFAULT_REMOTE_URL: &str = "https://github.com/openfootball/football.json.git";
+
+/// Files that differ between two revisions of football.json. Paths are relative to the repository root.
+#[derive(Default)]
+pub struct FileChanges {
+    /// Files that were added or modified
+    pub changed: Vec<PathBuf>,
+    /// Files that no longer exist
+    pub deleted: Vec<PathBuf>,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.deleted.is_empty()
+    }
+
+    /// League and groups files are loaded together. When one of them changed, the other one
+    /// (if it still exists below `root_dir`) is marked as changed as well.
+    pub fn add_companion_files(&mut self, root_dir: &Path) {
+        let companions: Vec<PathBuf> = self.changed.iter()
+            .chain(self.deleted.iter())
+            .filter_map(|p| companion_file(p))
+            .filter(|p| root_dir.join(p).is_file())
+            .collect();
+
+        for companion in companions {
+            if !self.changed.contains(&companion) {
+                self.changed.push(companion);
+            }
+        }
+    }
+}
+
+/// Manages the local git checkout of football.json
+pub struct SourceManager {
+    repo_path: PathBuf,
+    remote_url: String,
+    revision: Option<String>,
+}
+
+impl SourceManager {
+    pub fn new<P: AsRef<Path>>(repo_path: P) -> Self {
+        SourceManager {
+            repo_path: repo_path.as_ref().to_path_buf(),
+            remote_url: DEFAULT_REMOTE_URL.to_string(),
+            revision: None,
+        }
+    }
+
+    /// Sets the URL to clone and fetch from. Local mirrors (file://) are supported.
+    pub fn remote_url(mut self, url: &str) -> Self {
+        self.remote_url = url.to_string();
+        self
+    }
+
+    /// Pins the checkout to a tag, branch or commit
+    pub fn revision(mut self, revision: Option<String>) -> Self {
+        self.revision = revision;
+        self
+    }
+
+    pub fn get_repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    pub fn get_remote_url(&self) -> &str {
+        &self.remote_url
+    }
+
+    /// Clones the repository if it does not exist yet. If `update` is set, an existing
+    /// clone is fetched and fast-forwarded. Returns the SHA of the checked out commit.
+    pub fn sync(&self, update: bool) -> Result<String, Error> {
+        let repo = if self.repo_path.exists() {
+            info!("Using a cached copy of football.json at: {}", self.repo_path.to_string_lossy());
+            let repo = Repository::open(&self.repo_path)?;
+            if update {
+                self.fetch(&repo)?;
+            }
+            repo
+        } else {
+            info!("Cloning {} into: {}", self.remote_url, self.repo_path.to_string_lossy());
+            Repository::clone(&self.remote_url, &self.repo_path)?
+        };
+
+        match &self.revision {
+            Some(rev) => {
+                if let Err(e) = self.checkout_revision(&repo, rev) {
+                    if update {
+                        return Err(e);
+                    }
+
+                    info!("{} not found locally. Fetching from origin", rev);
+                    self.fetch(&repo)?;
+                    self.checkout_revision(&repo, rev)?;
+                }
+            },
+            None if update => self.fast_forward(&repo)?,
+            None => (),
+        };
+
+        self.head_commit_sha(&repo)
+    }
+
+    /// Returns the SHA of the currently checked out commit without touching the repository
+    pub fn current_commit(&self) -> Result<String, Error> {
+        let repo = Repository::open(&self.repo_path)?;
+        self.head_commit_sha(&repo)
+    }
+
+    /// Lists the files that changed between `since` and the currently checked out commit
+    pub fn changed_files(&self, since: &str) -> Result<FileChanges, Error> {
+        let repo = Repository::open(&self.repo_path)?;
+        let old_tree = repo.revparse_single(since)?.peel_to_tree()?;
+        let new_tree = repo.head()?.peel_to_tree()?;
+        let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+
+        let mut changes = FileChanges::default();
+        for delta in diff.deltas() {
+            let old_path = delta.old_file().path().map(|p| p.to_path_buf());
+            let new_path = delta.new_file().path().map(|p| p.to_path_buf());
+
+            match delta.status() {
+                Delta::Added | Delta::Modified | Delta::Copied | Delta::Typechange => {
+                    changes.changed.extend(new_path);
+                },
+                Delta::Deleted => {
+                    changes.deleted.extend(old_path);
+                },
+                Delta::Renamed => {
+                    changes.deleted.extend(old_path);
+                    changes.changed.extend(new_path);
+                },
+                _ => (),
+            };
+        }
+
+        info!("{} files changed and {} files deleted since {}", changes.changed.len(), changes.deleted.len(), since);
+        Ok(changes)
+    }
+
+    fn head_commit_sha(&self, repo: &Repository) -> Result<String, Error> {
+        let commit = repo.head()?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    /// Fetches all branches and tags from the configured remote into origin
+    fn fetch(&self, repo: &Repository) -> Result<(), Error> {
+        match repo.find_remote("origin") {
+            Ok(remote) => {
+                if remote.url() != Some(self.remote_url.as_str()) {
+                    info!("Changing origin to: {}", self.remote_url);
+                    repo.remote_set_url("origin", &self.remote_url)?;
+                }
+            },
+            Err(_) => {
+                repo.remote("origin", &self.remote_url)?;
+            }
+        };
+
+        info!("Fetching from: {}", self.remote_url);
+        let mut remote = repo.find_remote("origin")?;
+        remote.fetch(
+            &["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"],
+            None,
+            None
+        )?;
+
+        Ok(())
+    }
+
+    /// Moves the current branch forward to its counterpart on origin
+    fn fast_forward(&self, repo: &Repository) -> Result<(), Error> {
+        let head = repo.head()?;
+        let branch_name = match head.shorthand() {
+            Some(name) if head.is_branch() => name.to_string(),
+            _ => {
+                warn!("HEAD is detached. Checking out the default branch of origin instead");
+                let oid = self.origin_default_commit(repo)?;
+                return self.checkout_commit(repo, oid);
+            }
+        };
+
+        let upstream = repo.find_reference(&format!("refs/remotes/origin/{}", branch_name))?;
+        let upstream_commit = repo.reference_to_annotated_commit(&upstream)?;
+        let (analysis, _) = repo.merge_analysis(&[&upstream_commit])?;
+
+        if analysis.is_up_to_date() {
+            info!("football.json is already up to date");
+            return Ok(());
+        }
+
+        if !analysis.is_fast_forward() {
+            return Err(Error::from_str(&format!("Cannot fast-forward {} to origin/{}", branch_name, branch_name)));
+        }
+
+        info!("Fast-forwarding {} to: {}", branch_name, upstream_commit.id());
+        let mut branch_ref = repo.find_reference(&format!("refs/heads/{}", branch_name))?;
+        branch_ref.set_target(upstream_commit.id(), "football_json_loader: fast-forward")?;
+        repo.set_head(&format!("refs/heads/{}", branch_name))?;
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+
+        Ok(())
+    }
+
+    /// Finds the commit that origin's default branch points to
+    fn origin_default_commit(&self, repo: &Repository) -> Result<Oid, Error> {
+        for name in ["refs/remotes/origin/HEAD", "refs/remotes/origin/master", "refs/remotes/origin/main"] {
+            if let Ok(reference) = repo.find_reference(name) {
+                return Ok(reference.resolve()?.peel_to_commit()?.id());
+            }
+        }
+
+        Err(Error::from_str("Could not find the default branch of origin"))
+    }
+
+    /// Checks out a tag, branch or commit as a detached HEAD
+    fn checkout_revision(&self, repo: &Repository, revision: &str) -> Result<(), Error> {
+        let candidates = [
+            format!("refs/remotes/origin/{}", revision),
+            format!("refs/tags/{}", revision),
+            revision.to_string(),
+        ];
+
+        for candidate in candidates.iter() {
+            if let Ok(object) = repo.revparse_single(candidate) {
+                let commit = object.peel_to_commit()?;
+                info!("Checking out {} ({})", revision, commit.id());
+                return self.checkout_commit(repo, commit.id());
+            }
+        }
+
+        Err(Error::from_str(&format!("Could not resolve revision: {}", revision)))
+    }
+
+    fn checkout_commit(&self, repo: &Repository, oid: Oid) -> Result<(), Error> {
+        let commit = repo.find_commit(oid)?;
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+        repo.set_head_detached(oid)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,518 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fmt, str::FromStr};
+
+use chrono::NaiveDate;
+
+/// Points awarded for a win, a draw and a loss
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PointsRule {
+    pub win: i32,
+    pub draw: i32,
+    pub loss: i32,
+}
+
+impl PointsRule {
+    /// Three points for a win, used by most competitions since the 1990s
+    pub const THREE_FOR_A_WIN: PointsRule = PointsRule { win: 3, draw: 1, loss: 0 };
+    /// Two points for a win, used by most competitions before that
+    pub const TWO_FOR_A_WIN: PointsRule = PointsRule { win: 2, draw: 1, loss: 0 };
+}
+
+impl Default for PointsRule {
+    fn default() -> Self {
+        PointsRule::THREE_FOR_A_WIN
+    }
+}
+
+impl FromStr for PointsRule {
+    type Err = String;
+
+    /// Parses points written as win/draw/loss, e.g. `3/1/0`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid points: {} (expected win/draw/loss, e.g. 3/1/0)", s);
+
+        let points = s.split('/')
+            .map(|p| p.trim().parse::<i32>().map_err(|_| error()))
+            .collect::<Result<Vec<i32>, String>>()?;
+
+        match points[..] {
+            [win, draw, loss] => Ok(PointsRule { win, draw, loss }),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for PointsRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.win, self.draw, self.loss)
+    }
+}
+
+/// A way of ordering teams that are level on points
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TieBreaker {
+    GoalDifference,
+    GoalsFor,
+    Wins,
+    /// Points in the matches between the tied teams
+    HeadToHeadPoints,
+    /// Goal difference in the matches between the tied teams
+    HeadToHeadGoalDifference,
+    /// Goals scored in the matches between the tied teams
+    HeadToHeadGoalsFor,
+}
+
+impl TieBreaker {
+    /// Short name, as used on the command line and in the metadata table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TieBreaker::GoalDifference => "gd",
+            TieBreaker::GoalsFor => "gf",
+            TieBreaker::Wins => "wins",
+            TieBreaker::HeadToHeadPoints => "h2h",
+            TieBreaker::HeadToHeadGoalDifference => "h2h-gd",
+            TieBreaker::HeadToHeadGoalsFor => "h2h-gf",
+        }
+    }
+
+    fn is_head_to_head(&self) -> bool {
+        matches!(self, TieBreaker::HeadToHeadPoints | TieBreaker::HeadToHeadGoalDifference | TieBreaker::HeadToHeadGoalsFor)
+    }
+}
+
+impl FromStr for TieBreaker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gd" => Ok(TieBreaker::GoalDifference),
+            "gf" => Ok(TieBreaker::GoalsFor),
+            "wins" => Ok(TieBreaker::Wins),
+            "h2h" => Ok(TieBreaker::HeadToHeadPoints),
+            "h2h-gd" => Ok(TieBreaker::HeadToHeadGoalDifference),
+            "h2h-gf" => Ok(TieBreaker::HeadToHeadGoalsFor),
+            _ => Err(format!("Unknown tie-breaker: {} (expected gd, gf, wins, h2h, h2h-gd or h2h-gf)", s)),
+        }
+    }
+}
+
+/// Parses a comma separated chain of tie-breakers, e.g. `h2h,gd,gf`
+pub fn parse_tie_breakers(s: &str) -> Result<Vec<TieBreaker>, String> {
+    s.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(TieBreaker::from_str)
+        .collect()
+}
+
+/// How a league table is computed
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StandingsRules {
+    pub points: PointsRule,
+    /// Applied in order until the tied teams are separated
+    pub tie_breakers: Vec<TieBreaker>,
+}
+
+impl StandingsRules {
+    pub fn new(points: PointsRule, tie_breakers: Vec<TieBreaker>) -> Self {
+        StandingsRules { points, tie_breakers }
+    }
+
+    /// The tie-breakers as a comma separated list, e.g. `gd,gf`
+    pub fn tie_breakers_string(&self) -> String {
+        self.tie_breakers.iter().map(|t| t.as_str()).collect::<Vec<&str>>().join(",")
+    }
+}
+
+impl Default for StandingsRules {
+    /// Three points for a win, then goal difference and goals scored
+    fn default() -> Self {
+        StandingsRules::new(PointsRule::default(), vec![TieBreaker::GoalDifference, TieBreaker::GoalsFor])
+    }
+}
+
+/// The score of a played match, as needed to compute standings
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MatchResult {
+    pub date: NaiveDate,
+    /// Name of the round, e.g. `Matchday 12`
+    pub round: String,
+    /// Name of the stage, e.g. `Group stage` or `Knockout`, if the file gives one
+    pub stage: Option<String>,
+    pub team1_id: i32,
+    pub team2_id: i32,
+    pub team1_score: i32,
+    pub team2_score: i32,
+}
+
+/// A team's line in a league table
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct StandingsRow {
+    /// Teams that can't be separated by the tie-breakers share a position
+    pub position: i32,
+    pub team_id: i32,
+    pub played: i32,
+    pub won: i32,
+    pub drawn: i32,
+    pub lost: i32,
+    pub goals_for: i32,
+    pub goals_against: i32,
+    pub points: i32,
+}
+
+impl StandingsRow {
+    fn new(team_id: i32) -> Self {
+        StandingsRow { team_id, ..StandingsRow::default() }
+    }
+
+    pub fn goal_difference(&self) -> i32 {
+        self.goals_for - self.goals_against
+    }
+
+    fn add_result(&mut self, goals_for: i32, goals_against: i32, points: &PointsRule) {
+        self.played += 1;
+        self.goals_for += goals_for;
+        self.goals_against += goals_against;
+
+        if goals_for > goals_against {
+            self.won += 1;
+            self.points += points.win;
+        } else if goals_for == goals_against {
+            self.drawn += 1;
+            self.points += points.draw;
+        } else {
+            self.lost += 1;
+            self.points += points.loss;
+        }
+    }
+}
+
+/// Computes a league table from the results of its matches. Every team that played at least one of the
+/// matches is listed, ordered by points and then by the tie-breakers of the rules.
+pub fn compute_standings(results: &[MatchResult], rules: &StandingsRules) -> Vec<StandingsRow> {
+    let rows: Vec<StandingsRow> = tally(results, None, &rules.points).into_values().collect();
+    let tiers: Vec<Vec<StandingsRow>> = split_by(rows, |row| row.points).into_iter()
+        .flat_map(|tied| rank(tied, &rules.tie_breakers, results, &rules.points))
+        .collect();
+
+    // Teams still tied after every tie-breaker share a position and are listed by ID, so the order is stable
+    let mut standings = Vec::new();
+    for mut tier in tiers {
+        let position = standings.len() as i32 + 1;
+        tier.sort_by_key(|row| row.team_id);
+
+        for mut row in tier {
+            row.position = position;
+            standings.push(row);
+        }
+    }
+
+    standings
+}
+
+/// Computes a league table from the matches played on or before a date
+pub fn standings_at_date(results: &[MatchResult], date: NaiveDate, rules: &StandingsRules) -> Vec<StandingsRow> {
+    let played: Vec<MatchResult> = results.iter().filter(|r| r.date <= date).cloned().collect();
+    compute_standings(&played, rules)
+}
+
+/// Computes a league table from the matches of the first `matchday` rounds, as numbered by `number_rounds`.
+/// Matches of those rounds count even if they were postponed until after a later round.
+pub fn standings_after_round(results: &[MatchResult], matchday: i32, rules: &StandingsRules) -> Vec<StandingsRow> {
+    compute_standings(&played_by_round(results, &number_rounds(results), matchday), rules)
+}
+
+fn played_by_round(results: &[MatchResult], numbers: &HashMap<String, i32>, matchday: i32) -> Vec<MatchResult> {
+    results.iter()
+        .filter(|r| numbers.get(&r.round).is_some_and(|n| *n <= matchday))
+        .cloned()
+        .collect()
+}
+
+/// The table of a league after one of its rounds
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RoundStandings {
+    pub matchday: i32,
+    /// Name of the round, e.g. `Matchday 12`
+    pub round: String,
+    pub rows: Vec<StandingsRow>,
+}
+
+/// Computes the table after every round of a league, in order, e.g. to follow the positions of the teams over a season
+pub fn standings_by_round(results: &[MatchResult], rules: &StandingsRules) -> Vec<RoundStandings> {
+    let numbers = number_rounds(results);
+
+    // Rounds sharing a number are listed under the name of the first one
+    let mut rounds: BTreeMap<i32, &str> = BTreeMap::new();
+    for result in results {
+        if let Some(matchday) = numbers.get(&result.round) {
+            rounds.entry(*matchday).or_insert(&result.round);
+        }
+    }
+
+    rounds.into_iter()
+        .map(|(matchday, round)| RoundStandings {
+            matchday,
+            round: round.to_string(),
+            rows: compute_standings(&played_by_round(results, &numbers, matchday), rules),
+        })
+        .collect()
+}
+
+/// Numbers the rounds of a league. If every round is named after its number (`Matchday 12`, `Round 3`), that number
+/// is used. Otherwise, e.g. for cup rounds like `Quarter-finals`, rounds are numbered in the order they were first
+/// played.
+pub fn number_rounds(results: &[MatchResult]) -> HashMap<String, i32> {
+    let names: HashSet<&str> = results.iter().map(|r| r.round.as_str()).collect();
+    let numbered: HashMap<String, i32> = names.iter()
+        .filter_map(|name| Some((name.to_string(), number_in_name(name)?)))
+        .collect();
+
+    if numbered.len() == names.len() {
+        return numbered;
+    }
+
+    let mut first_played: HashMap<&str, NaiveDate> = HashMap::new();
+    for result in results {
+        first_played.entry(&result.round)
+            .and_modify(|d| *d = (*d).min(result.date))
+            .or_insert(result.date);
+    }
+
+    let mut rounds: Vec<(&str, NaiveDate)> = first_played.into_iter().collect();
+    rounds.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+
+    rounds.into_iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.to_string(), i as i32 + 1))
+        .collect()
+}
+
+/// Words rounds are numbered with, e.g. `Matchday 12`. Cup rounds like `Round of 16` are not numbered.
+const ROUND_WORDS: [&str; 7] = ["matchday", "round", "week", "day", "spieltag", "jornada", "giornata"];
+
+/// The number of a round named like `Matchday 12`
+fn number_in_name(name: &str) -> Option<i32> {
+    let (word, number) = name.trim().rsplit_once(' ')?;
+    if !ROUND_WORDS.contains(&word.trim().to_lowercase().as_str()) {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+/// Adds up the results per team. If `teams` is given, only matches between those teams count.
+fn tally(results: &[MatchResult], teams: Option<&HashSet<i32>>, points: &PointsRule) -> HashMap<i32, StandingsRow> {
+    let mut rows: HashMap<i32, StandingsRow> = HashMap::new();
+
+    for result in results {
+        if teams.is_some_and(|t| !t.contains(&result.team1_id) || !t.contains(&result.team2_id)) {
+            continue;
+        }
+
+        rows.entry(result.team1_id)
+            .or_insert_with(|| StandingsRow::new(result.team1_id))
+            .add_result(result.team1_score, result.team2_score, points);
+        rows.entry(result.team2_id)
+            .or_insert_with(|| StandingsRow::new(result.team2_id))
+            .add_result(result.team2_score, result.team1_score, points);
+    }
+
+    rows
+}
+
+/// Sorts rows by a key, highest first, and splits them into runs with the same key
+fn split_by<F: Fn(&StandingsRow) -> i32>(mut rows: Vec<StandingsRow>, key: F) -> Vec<Vec<StandingsRow>> {
+    rows.sort_by_key(|row| std::cmp::Reverse(key(row)));
+
+    let mut runs: Vec<Vec<StandingsRow>> = Vec::new();
+    for row in rows {
+        match runs.last_mut() {
+            Some(run) if key(&run[0]) == key(&row) => run.push(row),
+            _ => runs.push(vec![row]),
+        }
+    }
+
+    runs
+}
+
+/// Orders teams that are level, applying the tie-breakers one at a time to the teams that are still level.
+/// Returns the teams in order, grouped into runs that could not be separated.
+fn rank(tied: Vec<StandingsRow>, tie_breakers: &[TieBreaker], results: &[MatchResult], points: &PointsRule) -> Vec<Vec<StandingsRow>> {
+    let (tie_breaker, rest) = match tie_breakers.split_first() {
+        Some(t) if tied.len() > 1 => t,
+        _ => return vec![tied],
+    };
+
+    // Head-to-head records only count the matches between the teams that are still level
+    let head_to_head = if tie_breaker.is_head_to_head() {
+        let teams: HashSet<i32> = tied.iter().map(|row| row.team_id).collect();
+        tally(results, Some(&teams), points)
+    } else {
+        HashMap::new()
+    };
+
+    let key = |row: &StandingsRow| -> i32 {
+        let mini = head_to_head.get(&row.team_id).cloned().unwrap_or_default();
+        match tie_breaker {
+            TieBreaker::GoalDifference => row.goal_difference(),
+            TieBreaker::GoalsFor => row.goals_for,
+            TieBreaker::Wins => row.won,
+            TieBreaker::HeadToHeadPoints => mini.points,
+            TieBreaker::HeadToHeadGoalDifference => mini.goal_difference(),
+            TieBreaker::HeadToHeadGoalsFor => mini.goals_for,
+        }
+    };
+
+    split_by(tied, key).into_iter()
+        .flat_map(|run| rank(run, rest, results, points))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(date: &str, round: &str, team1_id: i32, team2_id: i32, team1_score: i32, team2_score: i32) -> MatchResult {
+        MatchResult {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            round: round.to_string(),
+            stage: None,
+            team1_id,
+            team2_id,
+            team1_score,
+            team2_score,
+        }
+    }
+
+    /// (position, team ID) of every row
+    fn positions(standings: &[StandingsRow]) -> Vec<(i32, i32)> {
+        standings.iter().map(|row| (row.position, row.team_id)).collect()
+    }
+
+    #[test]
+    fn counts_results_and_points() {
+        let results = vec![
+            result("2020-09-12", "Matchday 1", 1, 2, 3, 0),
+            result("2020-09-19", "Matchday 2", 2, 1, 1, 1),
+        ];
+
+        let standings = compute_standings(&results, &StandingsRules::default());
+        assert_eq!(standings[0], StandingsRow { position: 1, team_id: 1, played: 2, won: 1, drawn: 1, lost: 0, goals_for: 4, goals_against: 1, points: 4 });
+        assert_eq!(standings[1], StandingsRow { position: 2, team_id: 2, played: 2, won: 0, drawn: 1, lost: 1, goals_for: 1, goals_against: 4, points: 1 });
+
+        let rules = StandingsRules::new(PointsRule::TWO_FOR_A_WIN, Vec::new());
+        assert_eq!(compute_standings(&results, &rules)[0].points, 3);
+    }
+
+    #[test]
+    fn teams_that_cannot_be_separated_share_a_position() {
+        let results = vec![
+            result("2020-09-12", "Matchday 1", 2, 1, 1, 1),
+            result("2020-09-12", "Matchday 1", 3, 4, 2, 0),
+        ];
+
+        let standings = compute_standings(&results, &StandingsRules::default());
+        assert_eq!(positions(&standings), vec![(1, 3), (2, 1), (2, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn tie_breakers_are_applied_in_order() {
+        // Level on points and goal difference, 2 scored more
+        let results = vec![
+            result("2020-09-12", "Matchday 1", 1, 3, 1, 0),
+            result("2020-09-12", "Matchday 1", 2, 4, 3, 2),
+        ];
+
+        let gd_only = StandingsRules::new(PointsRule::default(), vec![TieBreaker::GoalDifference]);
+        assert_eq!(positions(&compute_standings(&results, &gd_only))[..2], [(1, 1), (1, 2)]);
+
+        let gd_gf = StandingsRules::default();
+        assert_eq!(positions(&compute_standings(&results, &gd_gf))[..2], [(1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn head_to_head_only_counts_teams_still_level() {
+        // 1, 2 and 3 are level on 6 points. 1 won both of its matches against the other two, which leaves 2 and 3
+        // level on head-to-head points. Between just the two of them, 3 has the better goal difference, while
+        // counting the matches against 1 as well, 2 would be ahead.
+        let results = vec![
+            result("2020-09-12", "Matchday 1", 1, 2, 3, 0),
+            result("2020-09-12", "Matchday 1", 3, 4, 1, 0),
+            result("2020-09-19", "Matchday 2", 1, 3, 6, 0),
+            result("2020-09-19", "Matchday 2", 2, 4, 1, 0),
+            result("2020-09-26", "Matchday 3", 2, 3, 1, 0),
+            result("2020-09-26", "Matchday 3", 4, 1, 1, 0),
+            result("2020-10-03", "Matchday 4", 3, 2, 2, 0),
+        ];
+
+        let rules = StandingsRules::new(PointsRule::default(), vec![TieBreaker::HeadToHeadPoints, TieBreaker::HeadToHeadGoalDifference]);
+        assert_eq!(positions(&compute_standings(&results, &rules)), vec![(1, 1), (2, 3), (3, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn parses_points_and_tie_breakers() {
+        assert_eq!("2/1/0".parse::<PointsRule>(), Ok(PointsRule::TWO_FOR_A_WIN));
+        assert!("3/1".parse::<PointsRule>().is_err());
+        assert_eq!(PointsRule::THREE_FOR_A_WIN.to_string(), "3/1/0");
+
+        assert_eq!(parse_tie_breakers("h2h, gd,gf"), Ok(vec![TieBreaker::HeadToHeadPoints, TieBreaker::GoalDifference, TieBreaker::GoalsFor]));
+        assert!(parse_tie_breakers("gd,away").is_err());
+        assert_eq!(StandingsRules::default().tie_breakers_string(), "gd,gf");
+    }
+
+    #[test]
+    fn rounds_are_numbered_by_name() {
+        // Matchday 2 was played before Matchday 1, which was postponed
+        let results = vec![
+            result("2020-09-19", "Matchday 2", 1, 2, 1, 0),
+            result("2020-10-03", "Matchday 1", 2, 1, 2, 0),
+        ];
+
+        let numbers = number_rounds(&results);
+        assert_eq!(numbers["Matchday 1"], 1);
+        assert_eq!(numbers["Matchday 2"], 2);
+    }
+
+    #[test]
+    fn cup_rounds_are_numbered_by_date() {
+        let results = vec![
+            result("2021-07-11", "Final", 1, 2, 1, 1),
+            result("2021-06-26", "Round of 16", 1, 3, 2, 0),
+            result("2021-07-02", "Quarter-finals", 1, 4, 1, 0),
+        ];
+
+        let numbers = number_rounds(&results);
+        assert_eq!(numbers["Round of 16"], 1);
+        assert_eq!(numbers["Quarter-finals"], 2);
+        assert_eq!(numbers["Final"], 3);
+    }
+
+    #[test]
+    fn round_names_without_a_round_word_are_not_numbers() {
+        assert_eq!(number_in_name("Matchday 12"), Some(12));
+        assert_eq!(number_in_name("Spieltag 3"), Some(3));
+        assert_eq!(number_in_name("Round of 16"), None);
+        assert_eq!(number_in_name("Final"), None);
+    }
+
+    #[test]
+    fn standings_after_round_include_postponed_matches() {
+        let results = vec![
+            result("2020-09-12", "Matchday 1", 1, 2, 1, 0),
+            result("2020-09-19", "Matchday 2", 3, 1, 0, 2),
+            result("2020-09-26", "Matchday 1", 3, 4, 1, 1),
+        ];
+
+        let after_round_1 = standings_after_round(&results, 1, &StandingsRules::default());
+        assert_eq!(after_round_1.iter().map(|row| row.played).sum::<i32>(), 4);
+
+        // On the date of Matchday 2 the postponed match wasn't played yet
+        let date = NaiveDate::from_ymd_opt(2020, 9, 19).unwrap();
+        let at_date = standings_at_date(&results, date, &StandingsRules::default());
+        assert!(!at_date.iter().any(|row| row.team_id == 4));
+
+        let by_round = standings_by_round(&results, &StandingsRules::default());
+        assert_eq!(by_round.iter().map(|r| (r.matchday, r.round.as_str())).collect::<Vec<_>>(), vec![(1, "Matchday 1"), (2, "Matchday 2")]);
+        assert_eq!(by_round[1].rows[0], StandingsRow { position: 1, team_id: 1, played: 2, won: 2, drawn: 0, lost: 0, goals_for: 3, goals_against: 0, points: 6 });
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, path::PathBuf, sync::Arc, vec};
+
+use crate::{model::{LeagueJsonFormat, OldLeagueMatch}, competition::Season, json::{parse_files, prepare_league}, report::IngestEntry};
+
+/// A league without its matches, e.g. to be stored in the leagues table
+#[derive(Clone, Debug)]
+pub struct LeagueInfo {
+    pub id: i32,
+    pub name: String,
+    /// Path of the file the league was loaded from, relative to the football.json root
+    pub source_file: String,
+    pub competition_code: Option<String>,
+    pub season: Option<Season>,
+}
+
+impl From<&LeagueJsonFormat> for LeagueInfo {
+    fn from(league: &LeagueJsonFormat) -> Self {
+        LeagueInfo {
+            id: league.get_league_id(),
+            name: league.get_league_name(),
+            source_file: league.get_source_file().to_string(),
+            competition_code: league.get_competition_code().map(|c| c.to_string()),
+            season: league.get_season().cloned(),
+        }
+    }
+}
+
+/// A match in the same shape whatever the format of the file it comes from
+pub struct MatchRecord {
+    pub league: Arc<LeagueInfo>,
+    /// Only given in the newer format, e.g. `Group stage`
+    pub stage: Option<String>,
+    pub round: String,
+    pub match_info: OldLeagueMatch,
+}
+
+/// All matches of a single league file
+pub struct LeagueMatches {
+    pub league: Arc<LeagueInfo>,
+    pub matches: Vec<MatchRecord>,
+}
+
+impl From<LeagueJsonFormat> for LeagueMatches {
+    fn from(league: LeagueJsonFormat) -> Self {
+        let info = Arc::new(LeagueInfo::from(&league));
+
+        let matches = match league {
+            LeagueJsonFormat::OldFormat(old_league) => old_league.rounds.into_iter()
+                .flat_map(|round| {
+                    let info = info.clone();
+                    round.matches.into_iter().map(move |m| MatchRecord {
+                        league: info.clone(),
+                        stage: None,
+                        round: round.name.clone(),
+                        match_info: m,
+                    })
+                })
+                .collect(),
+            LeagueJsonFormat::NewFormat(new_league) => new_league.matches.into_iter()
+                .map(|m| MatchRecord {
+                    league: info.clone(),
+                    stage: m.stage,
+                    round: m.round,
+                    match_info: m.match_info,
+                })
+                .collect(),
+        };
+
+        LeagueMatches { league: info, matches }
+    }
+}
+
+/// A league file still to be read by a LeagueStream
+pub(crate) struct PendingLeagueFile {
+    pub path: PathBuf,
+    pub source_file: String,
+    pub league_id: i32,
+}
+
+/// Reads league files one at a time, or `jobs` at a time when parsing in parallel, so memory use
+/// doesn't grow with the number of files. Files that can't be read or parsed are returned as errors.
+pub struct LeagueStream {
+    files: vec::IntoIter<PendingLeagueFile>,
+    jobs: usize,
+    parsed: VecDeque<Result<LeagueMatches, IngestEntry>>,
+}
+
+impl LeagueStream {
+    pub(crate) fn new(files: Vec<PendingLeagueFile>, jobs: usize) -> Self {
+        LeagueStream { files: files.into_iter(), jobs: jobs.max(1), parsed: VecDeque::new() }
+    }
+
+    /// Returns the matches one by one instead of per league file
+    pub fn matches(self) -> impl Iterator<Item = Result<MatchRecord, IngestEntry>> {
+        self.flat_map(|league| -> Box<dyn Iterator<Item = Result<MatchRecord, IngestEntry>>> {
+            match league {
+                Ok(l) => Box::new(l.matches.into_iter().map(Ok)),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }
+        })
+    }
+
+    fn parse_next_files(&mut self) {
+        let pending: Vec<PendingLeagueFile> = self.files.by_ref().take(self.jobs).collect();
+        let paths: Vec<PathBuf> = pending.iter().map(|f| f.path.clone()).collect();
+
+        for (file, (_, result)) in pending.into_iter().zip(parse_files::<LeagueJsonFormat>(&paths, self.jobs)) {
+            let parsed = match result {
+                Ok(mut league) => {
+                    prepare_league(&mut league, &file.path, file.source_file, file.league_id);
+                    Ok(LeagueMatches::from(league))
+                },
+                Err(error) => Err(IngestEntry { file: Some(file.source_file), item: None, error }),
+            };
+
+            self.parsed.push_back(parsed);
+        }
+    }
+}
+
+impl Iterator for LeagueStream {
+    type Item = Result<LeagueMatches, IngestEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.parsed.is_empty() {
+            self.parse_next_files();
+        }
+
+        self.parsed.pop_front()
+    }
+}